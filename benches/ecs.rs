@@ -0,0 +1,107 @@
+//! Per-generation cost of the ECS schedule with a persistent `NextCell`,
+//! against the previous design that added a `NextCell` to every entity and
+//! removed it again each tick.
+
+use std::time::{Duration, Instant};
+
+use bedelli::components::*;
+use bedelli::resources::*;
+use bedelli::systems::*;
+use bedelli::universe::{Simulation, Universe};
+use legion::systems::CommandBuffer;
+use legion::world::SubWorld;
+use legion::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const SIZE: i32 = 1000;
+
+#[system]
+#[read_component(Position)]
+fn churn_neighbor_counting(
+    world: &mut SubWorld,
+    cmd: &mut CommandBuffer,
+    #[resource] engine: &mut Engine,
+    #[resource] viewport: &Viewport,
+) {
+    engine.0.step();
+
+    let mut query = <(Entity, &Position)>::query();
+    for (entity, pos) in query.iter(world) {
+        let new_state = engine.0.get(viewport.x + pos.x as i64, viewport.y + pos.y as i64);
+        cmd.add_component(*entity, NextCell { state: new_state });
+    }
+}
+
+#[system]
+#[read_component(NextCell)]
+fn churn_cleanup_next(world: &mut SubWorld, cmd: &mut CommandBuffer) {
+    let mut query = <(Entity, &NextCell)>::query();
+    for (entity, _) in query.iter(world) {
+        cmd.remove_component::<NextCell>(*entity);
+    }
+}
+
+fn setup(with_next_cell: bool) -> (World, Resources) {
+    let dimensions = Dimensions { width: SIZE, height: SIZE };
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut universe = Universe::new(dimensions, Topology::TORUS, Rule::conway());
+    let mut world = World::default();
+    for x in 0..SIZE {
+        for y in 0..SIZE {
+            let state = rng.gen_range(0..2);
+            universe.set(x as i64, y as i64, state);
+            let cell = (Position { x, y }, Cell { state }, Age::default(), CellColor::default());
+            if with_next_cell {
+                world.push((cell.0, cell.1, NextCell { state }, cell.2, cell.3));
+            } else {
+                world.push(cell);
+            }
+        }
+    }
+
+    let mut resources = Resources::default();
+    resources.insert(dimensions);
+    resources.insert(Engine(Box::new(universe)));
+    resources.insert(Viewport::default());
+    resources.insert(InputState::default());
+    (world, resources)
+}
+
+fn time_per_generation(name: &str, generations: u32, schedule: &mut Schedule, with_next_cell: bool) -> Duration {
+    let (mut world, mut resources) = setup(with_next_cell);
+    schedule.execute(&mut world, &mut resources);
+    let start = Instant::now();
+    for _ in 0..generations {
+        schedule.execute(&mut world, &mut resources);
+    }
+    let per_generation = start.elapsed() / generations;
+    println!("{:<20} {:>12.3?} per generation", name, per_generation);
+    per_generation
+}
+
+fn main() {
+    println!("{}x{} random soup, B3/S23 on a torus", SIZE, SIZE);
+
+    let mut before = Schedule::builder()
+        .add_system(mouse_toggle_system())
+        .add_system(churn_neighbor_counting_system())
+        .flush()
+        .add_system(state_update_system())
+        .add_system(churn_cleanup_next_system())
+        .add_system(age_update_system())
+        .add_system(color_update_system())
+        .build();
+    let churn = time_per_generation("NextCell churn", 3, &mut before, false);
+
+    let mut after = Schedule::builder()
+        .add_system(mouse_toggle_system())
+        .add_system(neighbor_counting_system())
+        .add_system(state_update_system())
+        .add_system(age_update_system())
+        .add_system(color_update_system())
+        .build();
+    let in_place = time_per_generation("persistent NextCell", 3, &mut after, true);
+
+    println!("{:.1}x faster", churn.as_secs_f64() / in_place.as_secs_f64());
+}
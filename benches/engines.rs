@@ -0,0 +1,79 @@
+use std::time::{Duration, Instant};
+
+use bedelli::bitgrid::BitUniverse;
+use bedelli::components::*;
+use bedelli::resources::*;
+use bedelli::seeder::Grid;
+use bedelli::systems::*;
+use bedelli::universe::{Simulation, Universe};
+use legion::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const SIZE: i32 = 1000;
+
+fn random_grid(dimensions: Dimensions) -> Grid {
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut grid = Grid::new();
+    for x in 0..dimensions.width {
+        for y in 0..dimensions.height {
+            grid.insert((x, y), rng.gen_range(0..2));
+        }
+    }
+    grid
+}
+
+fn time_per_generation(name: &str, generations: u32, mut step: impl FnMut()) -> Duration {
+    step();
+    let start = Instant::now();
+    for _ in 0..generations {
+        step();
+    }
+    let per_generation = start.elapsed() / generations;
+    println!("{:<12} {:>12.3?} per generation", name, per_generation);
+    per_generation
+}
+
+fn main() {
+    let dimensions = Dimensions { width: SIZE, height: SIZE };
+    let grid = random_grid(dimensions);
+    println!("{}x{} random soup, B3/S23 on a torus", SIZE, SIZE);
+
+    let mut world = World::default();
+    let mut resources = Resources::default();
+    for (&(x, y), &state) in &grid {
+        let state = state as u8;
+        world.push((Position { x, y }, Cell { state }, NextCell { state }, Age::default(), CellColor::default()));
+    }
+    resources.insert(dimensions);
+    resources.insert(Engine(Box::new(Universe::from_grid(&grid, dimensions, Topology::TORUS, Rule::conway()))));
+    resources.insert(Viewport::default());
+    resources.insert(InputState::default());
+    let mut schedule = Schedule::builder()
+        .add_system(mouse_toggle_system())
+        .add_system(neighbor_counting_system())
+        .add_system(state_update_system())
+        .add_system(age_update_system())
+        .add_system(color_update_system())
+        .build();
+    let ecs = time_per_generation("ecs", 3, || schedule.execute(&mut world, &mut resources));
+
+    let mut cells = Universe::from_grid(&grid, dimensions, Topology::TORUS, Rule::conway());
+    time_per_generation("cells", 10, || cells.step());
+    let mut cells = Universe::from_grid(&grid, dimensions, Topology::TORUS, Rule::conway()).with_threads(0);
+    time_per_generation("cells (mt)", 10, || cells.step());
+
+    let mut bits = BitUniverse::from_grid(&grid, dimensions, Topology::TORUS, Rule::conway()).unwrap();
+    let packed = time_per_generation("bits", 100, || bits.step());
+    let mut bits = BitUniverse::from_grid(&grid, dimensions, Topology::TORUS, Rule::conway())
+        .unwrap()
+        .with_threads(0);
+    time_per_generation("bits (mt)", 100, || bits.step());
+
+    let corner: Grid = grid.range((0, 0)..(50, 0)).filter(|(&(_, y), _)| y < 50).map(|(&pos, &state)| (pos, state)).collect();
+    let mut sparse = Universe::from_grid(&corner, dimensions, Topology::TORUS, Rule::conway());
+    sparse.step_n(10);
+    time_per_generation("cells 50x50", 100, || sparse.step());
+
+    println!("bits is {:.0}x faster than ecs", ecs.as_secs_f64() / packed.as_secs_f64());
+}
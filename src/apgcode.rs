@@ -0,0 +1,277 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::neighbourhood::Neighbourhood;
+use crate::pattern::Pattern;
+use crate::resources::{Rule, RuleKind};
+use crate::seeder::Grid;
+use crate::sparse::SparseUniverse;
+use crate::universe::Simulation;
+
+/// Longest period `encode` looks for before giving up on an object.
+pub const MAX_PERIOD: u64 = 4096;
+
+/// The column digits of extended Wechsler format, one per 5-cell column.
+const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuv";
+
+/// Digits after `y`, each standing for 4 more blank columns than the last.
+const BLANK_RUNS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ApgcodeError {
+    Invalid(String),
+    UnsupportedRule(String),
+    Empty,
+    Aperiodic,
+}
+
+impl fmt::Display for ApgcodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApgcodeError::Invalid(code) => write!(f, "invalid apgcode '{}'", code),
+            ApgcodeError::UnsupportedRule(rule) => write!(f, "apgcodes need a two-state Moore rule without B0, not {}", rule),
+            ApgcodeError::Empty => write!(f, "object dies out"),
+            ApgcodeError::Aperiodic => write!(f, "object does not repeat within {} generations", MAX_PERIOD),
+        }
+    }
+}
+
+impl Error for ApgcodeError {}
+
+/// Tells whether `s` is shaped like an `xs`, `xp` or `xq` apgcode, so that
+/// it is not mistaken for a file name.
+pub fn is_apgcode(s: &str) -> bool {
+    split(s).is_some()
+}
+
+fn split(code: &str) -> Option<(char, u64, &str)> {
+    let rest = code.strip_prefix('x')?;
+    let kind = rest.chars().next().filter(|kind| matches!(kind, 's' | 'p' | 'q'))?;
+    let (number, body) = rest[1..].split_once('_')?;
+    Some((kind, number.parse().ok()?, body))
+}
+
+/// Classifies the live cells of `pattern` under `rule` as a still life
+/// (`xs` and its population), oscillator (`xp` and its period) or
+/// spaceship (`xq` and its period), and encodes its canonical phase and
+/// orientation as Catagolue does: the shortest extended Wechsler string
+/// over every phase and the 8 rotations and reflections, ties going to the
+/// first in ASCII order.
+pub fn encode(pattern: &Pattern, rule: Rule) -> Result<String, ApgcodeError> {
+    let counted = matches!(rule.kind(), RuleKind::Totalistic | RuleKind::NonTotalistic);
+    let moore = counted && rule.neighbourhood == Neighbourhood::default();
+    if rule.states > 2 || rule.birth & 1 != 0 || !moore {
+        return Err(ApgcodeError::UnsupportedRule(rule.to_string()));
+    }
+    let mut universe = SparseUniverse::from_grid(&pattern.cells, rule);
+    let start = universe.bounding_box().ok_or(ApgcodeError::Empty)?;
+    let size = |(x0, y0, x1, y1): (i64, i64, i64, i64)| (x1 - x0, y1 - y0);
+    let first = normalized(&universe);
+
+    for period in 1..=MAX_PERIOD {
+        universe.step();
+        let bounds = universe.bounding_box().ok_or(ApgcodeError::Empty)?;
+        if universe.population() != first.len() || size(bounds) != size(start) || normalized(&universe) != first {
+            continue;
+        }
+        let moved = (bounds.0, bounds.1) != (start.0, start.1);
+        let prefix = match (moved, period) {
+            (true, _) => format!("xq{}", period),
+            (false, 1) => format!("xs{}", first.len()),
+            (false, _) => format!("xp{}", period),
+        };
+        let mut phases = vec![first];
+        for _ in 1..period {
+            universe.step();
+            phases.push(normalized(&universe));
+        }
+        let body = phases
+            .iter()
+            .flat_map(|phase| orientations(phase))
+            .map(|cells| wechsler(&cells))
+            .min_by(|a, b| a.len().cmp(&b.len()).then_with(|| a.cmp(b)))
+            .unwrap_or_default();
+        return Ok(format!("{}_{}", prefix, body));
+    }
+    Err(ApgcodeError::Aperiodic)
+}
+
+/// The live cells of an apgcode's canonical phase, named after the code.
+pub fn decode(code: &str) -> Result<Pattern, ApgcodeError> {
+    let invalid = || ApgcodeError::Invalid(code.to_string());
+    let (_, _, body) = split(code).ok_or_else(invalid)?;
+
+    let mut grid = Grid::new();
+    let (mut x, mut strip) = (0, 0);
+    let mut chars = body.bytes();
+    while let Some(c) = chars.next() {
+        match c {
+            b'z' => {
+                x = 0;
+                strip += 1;
+            }
+            b'w' => x += 2,
+            b'x' => x += 3,
+            b'y' => {
+                let run = chars.next().and_then(|c| BLANK_RUNS.iter().position(|&d| d == c)).ok_or_else(invalid)?;
+                x += 4 + run as i32;
+            }
+            _ => {
+                let column = DIGITS.iter().position(|&d| d == c).ok_or_else(invalid)?;
+                for row in (0..5).filter(|row| column & (1 << row) != 0) {
+                    grid.insert((x, strip * 5 + row), 1);
+                }
+                x += 1;
+            }
+        }
+    }
+    let mut pattern = Pattern::from_grid(&grid);
+    pattern.name = Some(code.to_string());
+    Ok(pattern)
+}
+
+/// Live cells of `universe` moved so that their bounding box starts at the
+/// origin.
+fn normalized(universe: &SparseUniverse) -> Vec<(i32, i32)> {
+    let pattern = Pattern::from_cells(universe.cells().into_iter());
+    pattern.cells.keys().copied().collect()
+}
+
+/// The 8 rotations and reflections of `cells`, each moved back to the
+/// origin.
+fn orientations(cells: &[(i32, i32)]) -> Vec<Vec<(i32, i32)>> {
+    (0..8)
+        .map(|orientation| {
+            let grid: Grid = cells
+                .iter()
+                .map(|&(x, y)| {
+                    let (x, y) = if orientation & 4 != 0 { (y, x) } else { (x, y) };
+                    let x = if orientation & 1 != 0 { -x } else { x };
+                    let y = if orientation & 2 != 0 { -y } else { y };
+                    ((x, y), 1)
+                })
+                .collect();
+            Pattern::from_grid(&grid).cells.keys().copied().collect()
+        })
+        .collect()
+}
+
+/// Extended Wechsler format: the cells are cut into strips 5 rows tall,
+/// separated by `z`, and each column of a strip is a digit whose bit `i` is
+/// row `i`. Blank columns inside a strip become `0`, `w`, `x` or `y` and a
+/// count, and blank columns ending a strip are dropped.
+fn wechsler(cells: &[(i32, i32)]) -> String {
+    let width = cells.iter().map(|&(x, _)| x + 1).max().unwrap_or(0) as usize;
+    let strips = cells.iter().map(|&(_, y)| y / 5 + 1).max().unwrap_or(0) as usize;
+    let mut columns = vec![vec![0usize; width]; strips];
+    for &(x, y) in cells {
+        columns[y as usize / 5][x as usize] |= 1 << (y % 5);
+    }
+
+    let mut out = String::new();
+    for (index, strip) in columns.iter().enumerate() {
+        if index > 0 {
+            out.push('z');
+        }
+        let mut blanks = 0;
+        for &column in strip {
+            if column == 0 {
+                blanks += 1;
+                continue;
+            }
+            while blanks > 0 {
+                let run = blanks.min(39);
+                match run {
+                    1 => out.push('0'),
+                    2 => out.push('w'),
+                    3 => out.push('x'),
+                    _ => {
+                        out.push('y');
+                        out.push(BLANK_RUNS[run - 4] as char);
+                    }
+                }
+                blanks -= run;
+            }
+            out.push(DIGITS[column] as char);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::parse_rule;
+    use crate::rle;
+
+    fn encode_rle(text: &str) -> Result<String, ApgcodeError> {
+        encode(&rle::parse(text).unwrap(), Rule::conway())
+    }
+
+    #[test]
+    fn test_encode_still_lifes() {
+        assert_eq!(encode_rle("x = 2, y = 2\n2o$2o!"), Ok("xs4_33".to_string()));
+        assert_eq!(encode_rle("x = 4, y = 3\nb2o$o2bo$b2o!"), Ok("xs6_696".to_string()));
+        assert_eq!(encode_rle("x = 3, y = 3\n2o$obo$bo!"), Ok("xs5_253".to_string()));
+    }
+
+    #[test]
+    fn test_encode_oscillators() {
+        assert_eq!(encode_rle("x = 3, y = 1\n3o!"), Ok("xp2_7".to_string()));
+        assert_eq!(encode_rle("x = 4, y = 2\nb3o$3o!"), Ok("xp2_7e".to_string()));
+        assert_eq!(encode_rle("x = 4, y = 4\n2o$2o$2b2o$2b2o!"), Ok("xp2_318c".to_string()));
+    }
+
+    #[test]
+    fn test_encode_spaceships_from_any_phase_and_orientation() {
+        let gliders = ["x = 3, y = 3\nbo$2bo$3o!", "x = 3, y = 3\n3o$o$bo!", "x = 3, y = 3\nobo$b2o$bo!"];
+        for glider in gliders {
+            assert_eq!(encode_rle(glider), Ok("xq4_153".to_string()), "{}", glider);
+        }
+        assert_eq!(encode_rle("x = 5, y = 4\nbo2bo$o$o3bo$4o!"), Ok("xq4_6frc".to_string()));
+    }
+
+    #[test]
+    fn test_decode_round_trips() {
+        for code in ["xs4_33", "xs6_696", "xs7_2596", "xp2_7", "xp2_318c", "xq4_153", "xq4_6frc"] {
+            let pattern = decode(code).unwrap();
+            assert_eq!(pattern.name.as_deref(), Some(code));
+            assert_eq!(encode(&pattern, Rule::conway()), Ok(code.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_wechsler_blank_runs_and_strips() {
+        let pattern = decode("xp0_1w1x1y01y3121zx1").unwrap();
+        let cells: Vec<(i32, i32)> = pattern.cells.keys().copied().collect();
+        let expected = [(0, 0), (3, 0), (3, 5), (7, 0), (12, 0), (20, 0), (21, 1), (22, 0)];
+        assert_eq!(cells, expected);
+        assert_eq!(wechsler(&cells), "1w1x1y01y3121zx1");
+
+        let far = [(0, 0), (45, 0)];
+        assert_eq!(wechsler(&far), "1yzy11");
+        let grid: Grid = far.iter().map(|&pos| (pos, 1)).collect();
+        assert_eq!(decode("xs2_1yzy11").unwrap().cells, grid);
+    }
+
+    #[test]
+    fn test_is_apgcode() {
+        assert!(is_apgcode("xs4_33"));
+        assert!(is_apgcode("xq4_153"));
+        assert!(!is_apgcode("glider.rle"));
+        assert!(!is_apgcode("xs_33"));
+        assert!(!is_apgcode("xr4_33"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(decode("xs4_3!"), Err(ApgcodeError::Invalid("xs4_3!".to_string())));
+        assert_eq!(decode("xs4_y"), Err(ApgcodeError::Invalid("xs4_y".to_string())));
+        assert_eq!(encode_rle("x = 1, y = 1\no!"), Err(ApgcodeError::Empty));
+        let brain = parse_rule("B2/S/C3").unwrap();
+        assert!(matches!(encode(&decode("xs4_33").unwrap(), brain), Err(ApgcodeError::UnsupportedRule(_))));
+        // Under B2/S a domino splits into two that fly apart for ever.
+        let seeds = parse_rule("B2/S").unwrap();
+        assert_eq!(encode(&rle::parse("x = 2, y = 1\n2o!").unwrap(), seeds), Err(ApgcodeError::Aperiodic));
+    }
+}
@@ -0,0 +1,354 @@
+use crate::neighbourhood::Neighbourhood;
+use crate::resources::{Dimensions, Rule, RuleKind, Topology};
+use crate::seeder::Grid;
+use crate::universe::{EngineError, Simulation, Workers};
+
+/// A fixed-size board packed 64 cells to a `u64`, stepped with bitwise
+/// full adders that count the neighbours of a whole word of cells at once.
+///
+/// Only two-state outer-totalistic rules on a plane or an unshifted torus
+/// are supported.
+pub struct BitUniverse {
+    dimensions: Dimensions,
+    wrap: bool,
+    rule: Rule,
+    words_per_row: usize,
+    rows: Vec<u64>,
+    next: Vec<u64>,
+    generation: u64,
+    workers: Workers,
+}
+
+impl BitUniverse {
+    pub fn new(dimensions: Dimensions, topology: Topology, rule: Rule) -> Result<Self, EngineError> {
+        if rule.kind() != RuleKind::Totalistic || rule.states > 2 || rule.neighbourhood != Neighbourhood::default() {
+            return Err(EngineError::UnsupportedRule(rule.to_string()));
+        }
+        let wrap = match topology {
+            Topology::Plane => false,
+            Topology::TORUS => true,
+            other => return Err(EngineError::UnsupportedTopology(other)),
+        };
+
+        let words_per_row = (dimensions.width as usize).div_ceil(64);
+        let size = words_per_row * dimensions.height as usize;
+        Ok(BitUniverse {
+            dimensions,
+            wrap,
+            rule,
+            words_per_row,
+            rows: vec![0; size],
+            next: vec![0; size],
+            generation: 0,
+            workers: Workers::default(),
+        })
+    }
+
+    pub fn from_grid(grid: &Grid, dimensions: Dimensions, topology: Topology, rule: Rule) -> Result<Self, EngineError> {
+        let mut universe = BitUniverse::new(dimensions, topology, rule)?;
+        for (&(x, y), &state) in grid {
+            universe.set(x as i64, y as i64, state as u8);
+        }
+        Ok(universe)
+    }
+
+    /// Steps with `threads` threads, see `Workers::new`.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.workers = Workers::new(threads);
+        self
+    }
+
+    fn contains(&self, x: i64, y: i64) -> bool {
+        x >= 0 && x < self.dimensions.width as i64 && y >= 0 && y < self.dimensions.height as i64
+    }
+
+    fn row(&self, y: i32) -> Option<&[u64]> {
+        let height = self.dimensions.height;
+        let y = match y {
+            y if y >= 0 && y < height => y,
+            _ if self.wrap => y.rem_euclid(height),
+            _ => return None,
+        } as usize;
+        Some(&self.rows[y * self.words_per_row..(y + 1) * self.words_per_row])
+    }
+
+    /// Word `k` of `row` moved one cell east, so each bit holds its western
+    /// neighbour.
+    fn west(&self, row: &[u64], k: usize) -> u64 {
+        let carry = if k > 0 {
+            row[k - 1] >> 63
+        } else if self.wrap {
+            let last = self.dimensions.width as usize - 1;
+            (row[last / 64] >> (last % 64)) & 1
+        } else {
+            0
+        };
+        (row[k] << 1) | carry
+    }
+
+    /// Word `k` of `row` moved one cell west, so each bit holds its eastern
+    /// neighbour.
+    fn east(&self, row: &[u64], k: usize) -> u64 {
+        let mut word = row[k] >> 1;
+        if k + 1 < row.len() {
+            word |= row[k + 1] << 63;
+        } else if self.wrap {
+            let last = self.dimensions.width as usize - 1;
+            word |= (row[0] & 1) << (last % 64);
+        }
+        word
+    }
+
+    /// Positions of the set bits of the words `word` returns for each index.
+    fn positions(&self, word: impl Fn(usize) -> u64) -> Vec<(i64, i64)> {
+        let mut positions = Vec::new();
+        for index in 0..self.rows.len() {
+            let mut bits = word(index);
+            while bits != 0 {
+                let (y, k) = (index / self.words_per_row, index % self.words_per_row);
+                positions.push(((k * 64 + bits.trailing_zeros() as usize) as i64, y as i64));
+                bits &= bits - 1;
+            }
+        }
+        positions
+    }
+
+    fn last_word_mask(&self) -> u64 {
+        match self.dimensions.width % 64 {
+            0 => !0,
+            bits => (1 << bits) - 1,
+        }
+    }
+}
+
+fn half_add(a: u64, b: u64) -> (u64, u64) {
+    (a ^ b, a & b)
+}
+
+fn full_add(a: u64, b: u64, c: u64) -> (u64, u64) {
+    let partial = a ^ b;
+    (partial ^ c, (a & b) | (partial & c))
+}
+
+/// Adds eight one-bit neighbour words into a bit-sliced four-bit count,
+/// returned least significant bit first.
+fn count_neighbours(n: [u64; 8]) -> [u64; 4] {
+    let (sum_a, carry_a) = full_add(n[0], n[1], n[2]);
+    let (sum_b, carry_b) = full_add(n[3], n[4], n[5]);
+    let (sum_c, carry_c) = half_add(n[6], n[7]);
+    let (ones, carry_d) = full_add(sum_a, sum_b, sum_c);
+    let (partial_twos, fours_a) = full_add(carry_a, carry_b, carry_c);
+    let (twos, fours_b) = half_add(partial_twos, carry_d);
+    let (fours, eights) = half_add(fours_a, fours_b);
+    [ones, twos, fours, eights]
+}
+
+/// Word whose bits are set where the bit-sliced `count` equals `n`.
+fn count_equals(count: &[u64; 4], n: usize) -> u64 {
+    count
+        .iter()
+        .enumerate()
+        .fold(!0, |acc, (bit, &slice)| acc & if n & (1 << bit) != 0 { slice } else { !slice })
+}
+
+impl Simulation for BitUniverse {
+    fn step(&mut self) {
+        let words = self.words_per_row;
+        let empty = vec![0; words];
+        let last_word_mask = self.last_word_mask();
+        let births: Vec<usize> = (0..=8).filter(|&n| self.rule.birth & (1 << n) != 0).collect();
+        let survivals: Vec<usize> = (0..=8).filter(|&n| self.rule.survive & (1 << n) != 0).collect();
+
+        let mut next = std::mem::take(&mut self.next);
+        self.workers.for_each_row(&mut next, words, |y, next_row| {
+            let y = y as i32;
+            let up = self.row(y - 1).unwrap_or(&empty);
+            let middle = self.row(y).unwrap_or(&empty);
+            let down = self.row(y + 1).unwrap_or(&empty);
+            for (k, word) in next_row.iter_mut().enumerate() {
+                let count = count_neighbours([
+                    self.west(up, k),
+                    up[k],
+                    self.east(up, k),
+                    self.west(middle, k),
+                    self.east(middle, k),
+                    self.west(down, k),
+                    down[k],
+                    self.east(down, k),
+                ]);
+                let born = births.iter().fold(0, |acc, &n| acc | count_equals(&count, n));
+                let survived = survivals.iter().fold(0, |acc, &n| acc | count_equals(&count, n));
+                let alive = middle[k];
+                *word = (alive & survived) | (!alive & born);
+            }
+            next_row[words - 1] &= last_word_mask;
+        });
+
+        self.next = std::mem::replace(&mut self.rows, next);
+        self.generation += 1;
+    }
+
+    fn get(&self, x: i64, y: i64) -> u8 {
+        if !self.contains(x, y) {
+            return 0;
+        }
+        let word = self.rows[y as usize * self.words_per_row + x as usize / 64];
+        ((word >> (x % 64)) & 1) as u8
+    }
+
+    fn set(&mut self, x: i64, y: i64, state: u8) {
+        if !self.contains(x, y) {
+            return;
+        }
+        let word = &mut self.rows[y as usize * self.words_per_row + x as usize / 64];
+        let bit = 1 << (x % 64);
+        if state == 1 {
+            *word |= bit;
+        } else {
+            *word &= !bit;
+        }
+    }
+
+    fn population(&self) -> usize {
+        self.rows.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn cells(&self) -> Vec<((i64, i64), u8)> {
+        self.positions(|index| self.rows[index]).into_iter().map(|pos| (pos, 1)).collect()
+    }
+
+    fn changed_cells(&self) -> Option<Vec<(i64, i64)>> {
+        Some(self.positions(|index| self.rows[index] ^ self.next[index]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::parse_rule;
+    use crate::universe::Universe;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_grid(dimensions: Dimensions, seed: u64) -> Grid {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut grid = Grid::new();
+        for x in 0..dimensions.width {
+            for y in 0..dimensions.height {
+                grid.insert((x, y), rng.gen_range(0..2));
+            }
+        }
+        grid
+    }
+
+    fn assert_matches_universe(dimensions: Dimensions, topology: Topology, rule: Rule) {
+        let grid = random_grid(dimensions, 42);
+        let mut reference = Universe::from_grid(&grid, dimensions, topology, rule);
+        let mut packed = BitUniverse::from_grid(&grid, dimensions, topology, rule).unwrap();
+
+        for generation in 0..20 {
+            for y in 0..dimensions.height as i64 {
+                for x in 0..dimensions.width as i64 {
+                    assert_eq!(
+                        packed.get(x, y),
+                        reference.get(x, y),
+                        "{:?} {} {:?} at ({}, {}) generation {}",
+                        dimensions, rule, topology, x, y, generation
+                    );
+                }
+            }
+            assert_eq!(packed.population(), reference.population());
+            assert_eq!(packed.changed_cells(), reference.changed_cells());
+            assert_eq!(packed.cells(), reference.cells());
+            reference.step();
+            packed.step();
+        }
+    }
+
+    #[test]
+    fn test_count_neighbours_sums_every_input() {
+        for inputs in 0..256u32 {
+            let count = count_neighbours(std::array::from_fn(|bit| ((inputs >> bit) & 1) as u64));
+            let total = count[0] + 2 * count[1] + 4 * count[2] + 8 * count[3];
+            assert_eq!(total, inputs.count_ones() as u64);
+        }
+    }
+
+    #[test]
+    fn test_matches_universe_on_plane() {
+        for width in [1, 5, 63, 64, 65, 130] {
+            assert_matches_universe(Dimensions { width, height: 17 }, Topology::Plane, Rule::conway());
+        }
+    }
+
+    #[test]
+    fn test_matches_universe_on_torus() {
+        for width in [3, 63, 64, 65, 130] {
+            assert_matches_universe(Dimensions { width, height: 9 }, Topology::TORUS, Rule::conway());
+        }
+    }
+
+    #[test]
+    fn test_matches_universe_for_other_rules() {
+        let dimensions = Dimensions { width: 70, height: 20 };
+        for rule in ["B36/S23", "B3678/S34678", "B2/S", "B3/S012345678", "B0/S8", "B1357/S1357"] {
+            assert_matches_universe(dimensions, Topology::TORUS, parse_rule(rule).unwrap());
+            assert_matches_universe(dimensions, Topology::Plane, parse_rule(rule).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_threaded_steps_match_serial_steps() {
+        let dimensions = Dimensions { width: 200, height: 61 };
+        let grid = random_grid(dimensions, 7);
+        let mut serial = BitUniverse::from_grid(&grid, dimensions, Topology::TORUS, Rule::conway()).unwrap();
+        let mut threaded = BitUniverse::from_grid(&grid, dimensions, Topology::TORUS, Rule::conway())
+            .unwrap()
+            .with_threads(4);
+        for _ in 0..50 {
+            serial.step();
+            threaded.step();
+            assert_eq!(serial.rows, threaded.rows);
+        }
+    }
+
+    #[test]
+    fn test_rejects_unsupported_rules_and_topologies() {
+        let dimensions = Dimensions { width: 10, height: 10 };
+        let brians_brain = parse_rule("B2/S/C3").unwrap();
+        assert_eq!(
+            BitUniverse::new(dimensions, Topology::Plane, brians_brain).err(),
+            Some(EngineError::UnsupportedRule("B2/S/C3".to_string()))
+        );
+        let just_friends = parse_rule("B2-a/S12").unwrap();
+        assert_eq!(
+            BitUniverse::new(dimensions, Topology::Plane, just_friends).err(),
+            Some(EngineError::UnsupportedRule("B2-a/S12".to_string()))
+        );
+        let bosco = parse_rule("R5,C0,M1,S34..58,B34..45,NM").unwrap();
+        assert!(BitUniverse::new(dimensions, Topology::Plane, bosco).is_err());
+        assert_eq!(
+            BitUniverse::new(dimensions, Topology::CrossSurface, Rule::conway()).err(),
+            Some(EngineError::UnsupportedTopology(Topology::CrossSurface))
+        );
+    }
+
+    #[test]
+    fn test_set_and_get() {
+        let mut universe = BitUniverse::new(Dimensions { width: 100, height: 3 }, Topology::Plane, Rule::conway()).unwrap();
+        universe.set(99, 2, 1);
+        universe.set(100, 2, 1);
+        assert_eq!(universe.get(99, 2), 1);
+        assert_eq!(universe.population(), 1);
+        universe.set(99, 2, 0);
+        assert_eq!(universe.population(), 0);
+    }
+}
@@ -4,27 +4,36 @@ pub struct Position {
     pub y: i32,
 }
 
+/// A cell's state: 0 is dead, 1 is alive and, under Generations rules,
+/// 2 and up are the dying states it passes through before it is dead.
+/// Named rules such as Wireworld give each state their own meaning.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Cell {
-    pub alive: bool,
+    pub state: u8,
 }
 
+impl Cell {
+    pub const DEAD: Cell = Cell { state: 0 };
+    pub const ALIVE: Cell = Cell { state: 1 };
+
+    pub fn is_alive(&self) -> bool {
+        self.state == 1
+    }
+}
+
+/// The state a cell takes on at the end of the tick. Every cell keeps one
+/// and it is rewritten in place, so stepping never moves entities between
+/// archetypes.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct NextCell {
-    pub alive: bool,
+    pub state: u8,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Age {
     pub value: u32,
 }
 
-impl Default for Age {
-    fn default() -> Self {
-        Age { value: 0 }
-    }
-}
-
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct CellColor {
     pub r: u8,
@@ -65,11 +74,17 @@ mod tests {
 
     #[test]
     fn test_cell_creation() {
-        let cell = Cell { alive: true };
-        assert_eq!(cell.alive, true);
+        let cell = Cell::ALIVE;
+        assert!(cell.is_alive());
         
-        let dead_cell = Cell { alive: false };
-        assert_eq!(dead_cell.alive, false);
+        let dead_cell = Cell::DEAD;
+        assert!(!dead_cell.is_alive());
+    }
+
+    #[test]
+    fn test_dying_cell_is_not_alive() {
+        let cell = Cell { state: 2 };
+        assert!(!cell.is_alive());
     }
 
     #[test]
@@ -0,0 +1,243 @@
+use std::str::FromStr;
+
+use crate::pattern::{Pattern, PatternError, Placement, Rotation};
+use crate::resources::{Dimensions, Rule};
+use crate::seeder::Grid;
+use crate::sparse::SparseUniverse;
+use crate::universe::{EngineError, Simulation};
+
+/// A pattern as it goes onto the board: mirrored left to right if
+/// `reflected`, then turned by `rotation`, then run for `generations` under
+/// the board's rule, and placed by the box it had before running.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Layer {
+    pub pattern: Pattern,
+    pub placement: Placement,
+    pub rotation: Rotation,
+    pub reflected: bool,
+    pub generations: u64,
+}
+
+impl Layer {
+    pub fn new(pattern: Pattern) -> Self {
+        Layer { pattern, ..Layer::default() }
+    }
+
+    /// The layer's cells on a board of `dimensions` running `rule`. Running
+    /// the pattern first needs a rule without B0.
+    pub fn cells(&self, dimensions: Dimensions, rule: Rule) -> Result<Grid, EngineError> {
+        let mut pattern = if self.reflected { self.pattern.reflected() } else { self.pattern.clone() };
+        pattern = pattern.rotated(self.rotation);
+        if self.generations > 0 {
+            if rule.has_b0() {
+                return Err(EngineError::UnsupportedRule(rule.to_string()));
+            }
+            let mut universe = SparseUniverse::from_grid(&pattern.cells, rule);
+            universe.step_n(self.generations);
+            let cells = universe.cells().into_iter();
+            pattern.cells = cells.map(|((x, y), state)| ((x as i32, y as i32), state as i32)).collect();
+        }
+        Ok(pattern.place(dimensions, self.placement))
+    }
+}
+
+/// Any number of patterns put onto one board. Where layers overlap, the
+/// later one wins.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Composition {
+    pub layers: Vec<Layer>,
+}
+
+impl Composition {
+    pub fn new() -> Self {
+        Composition::default()
+    }
+
+    pub fn with(mut self, layer: Layer) -> Self {
+        self.layers.push(layer);
+        self
+    }
+
+    pub fn seed(&self, dimensions: Dimensions, rule: Rule) -> Result<Grid, EngineError> {
+        let mut grid = Grid::new();
+        for layer in &self.layers {
+            grid.extend(layer.cells(dimensions, rule)?);
+        }
+        Ok(grid)
+    }
+}
+
+/// One item of a composition spec, before its source is loaded. The
+/// syntax is `source[@x,y][:r90][:flip][:g12]`: the pattern, where its
+/// top-left corner goes, a clockwise rotation of 0, 90, 180 or 270, a left
+/// to right reflection and generations to run it for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LayerSpec {
+    pub source: String,
+    pub placement: Option<Placement>,
+    pub rotation: Rotation,
+    pub reflected: bool,
+    pub generations: u64,
+}
+
+impl LayerSpec {
+    /// The layer for `pattern`, placed by `placement` unless the spec says
+    /// where.
+    pub fn layer(&self, pattern: Pattern, placement: Placement) -> Layer {
+        Layer {
+            pattern,
+            placement: self.placement.unwrap_or(placement),
+            rotation: self.rotation,
+            reflected: self.reflected,
+            generations: self.generations,
+        }
+    }
+}
+
+impl FromStr for LayerSpec {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || PatternError::InvalidLayer(s.to_string());
+        let mut parts = s.trim().split(':');
+        let first = parts.next().unwrap_or_default();
+        let (source, placement) = match first.rsplit_once('@') {
+            Some((source, at)) => (source, Some(at.parse()?)),
+            None => (first, None),
+        };
+        if source.is_empty() {
+            return Err(invalid());
+        }
+        let mut spec = LayerSpec {
+            source: source.to_string(),
+            placement,
+            rotation: Rotation::None,
+            reflected: false,
+            generations: 0,
+        };
+        for modifier in parts {
+            match modifier.split_at(modifier.len().min(1)) {
+                ("r", degrees) => spec.rotation = degrees.parse()?,
+                ("g", generations) => spec.generations = generations.parse().map_err(|_| invalid())?,
+                _ if modifier == "flip" => spec.reflected = true,
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(spec)
+    }
+}
+
+/// Splits a `+`-separated composition spec into its layers.
+pub fn parse_spec(spec: &str) -> Result<Vec<LayerSpec>, PatternError> {
+    spec.split('+').map(str::parse).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library;
+
+    const BOARD: Dimensions = Dimensions { width: 40, height: 30 };
+
+    fn entry(name: &str) -> Pattern {
+        library::find(name).unwrap().pattern()
+    }
+
+    fn live(grid: &Grid) -> Vec<(i32, i32)> {
+        grid.keys().copied().collect()
+    }
+
+    #[test]
+    fn test_single_layer_matches_place() {
+        let glider = entry("glider");
+        let layer = Layer::new(glider.clone());
+        assert_eq!(layer.cells(BOARD, Rule::conway()).unwrap(), glider.place(BOARD, Placement::Centered));
+    }
+
+    #[test]
+    fn test_layer_runs_its_pattern_in_place() {
+        let glider = entry("glider");
+        let moved = Layer {
+            generations: 4,
+            placement: Placement::At(10, 10),
+            ..Layer::new(glider.clone())
+        };
+        let expected = glider.place(BOARD, Placement::At(11, 11));
+        assert_eq!(moved.cells(BOARD, Rule::conway()).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_running_a_layer_needs_a_rule_without_b0() {
+        let rule = crate::resources::parse_rule("B0/S8").unwrap();
+        let layer = Layer::new(entry("glider"));
+        assert!(layer.cells(BOARD, rule).is_ok());
+        let run = Layer { generations: 1, ..layer };
+        assert_eq!(run.cells(BOARD, rule), Err(EngineError::UnsupportedRule("B0/S8".to_string())));
+    }
+
+    #[test]
+    fn test_layer_reflects_then_rotates() {
+        let glider = entry("glider");
+        let layer = Layer {
+            rotation: Rotation::Quarter,
+            reflected: true,
+            placement: Placement::At(0, 0),
+            ..Layer::new(glider.clone())
+        };
+        let expected = glider.reflected().rotated(Rotation::Quarter).place(BOARD, Placement::At(0, 0));
+        assert_eq!(layer.cells(BOARD, Rule::conway()).unwrap(), expected);
+        assert_ne!(expected, glider.rotated(Rotation::Quarter).reflected().place(BOARD, Placement::At(0, 0)));
+    }
+
+    #[test]
+    fn test_composition_stacks_layers() {
+        let beacon = entry("beacon");
+        let composition = Composition::new()
+            .with(Layer { placement: Placement::At(0, 0), ..Layer::new(entry("glider")) })
+            .with(Layer { placement: Placement::At(30, 20), rotation: Rotation::Half, ..Layer::new(entry("glider")) })
+            .with(Layer { placement: Placement::At(2, 2), ..Layer::new(beacon) });
+        let grid = composition.seed(BOARD, Rule::conway()).unwrap();
+        assert_eq!(grid.len(), 5 + 5 + 8 - 1);
+        assert!(live(&grid).contains(&(32, 20)));
+        assert_eq!(grid.get(&(2, 2)), Some(&1));
+    }
+
+    #[test]
+    fn test_guns_aimed_at_each_other() {
+        let gun = entry("gosper-glider-gun");
+        let composition = Composition::new()
+            .with(Layer { placement: Placement::At(0, 0), ..Layer::new(gun.clone()) })
+            .with(Layer { placement: Placement::At(60, 40), rotation: Rotation::Half, ..Layer::new(gun) });
+        let board = Dimensions { width: 100, height: 60 };
+        let grid = composition.seed(board, Rule::conway()).unwrap();
+        assert_eq!(grid.len(), 72);
+        assert_eq!(grid.get(&(95, 44)), Some(&1));
+    }
+
+    #[test]
+    fn test_parse_spec() {
+        let specs = parse_spec("gosper-glider-gun@2,3+glider:r270:flip:g12+board.rle").unwrap();
+        assert_eq!(specs.len(), 3);
+        assert_eq!(specs[0].source, "gosper-glider-gun");
+        assert_eq!(specs[0].placement, Some(Placement::At(2, 3)));
+        assert_eq!(specs[1].source, "glider");
+        assert_eq!(specs[1].placement, None);
+        assert_eq!(specs[1].rotation, Rotation::ThreeQuarters);
+        assert!(specs[1].reflected);
+        assert_eq!(specs[1].generations, 12);
+        assert_eq!(specs[2].source, "board.rle");
+
+        let layer = specs[1].layer(entry("glider"), Placement::At(5, 5));
+        assert_eq!(layer.placement, Placement::At(5, 5));
+        assert_eq!(specs[0].layer(entry("glider"), Placement::At(5, 5)).placement, Placement::At(2, 3));
+    }
+
+    #[test]
+    fn test_parse_spec_errors() {
+        assert_eq!(parse_spec("glider:r45"), Err(PatternError::InvalidRotation("45".to_string())));
+        assert_eq!(parse_spec("glider:spin"), Err(PatternError::InvalidLayer("glider:spin".to_string())));
+        assert_eq!(parse_spec("glider:gx"), Err(PatternError::InvalidLayer("glider:gx".to_string())));
+        assert_eq!(parse_spec("glider+"), Err(PatternError::InvalidLayer("".to_string())));
+        assert_eq!(parse_spec("glider@3"), Err(PatternError::InvalidPlacement("3".to_string())));
+    }
+}
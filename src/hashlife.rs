@@ -0,0 +1,548 @@
+use std::collections::HashMap;
+
+use crate::resources::{neighbour_bit, Rule, RuleKind};
+use crate::seeder::Grid;
+use crate::universe::{EngineError, Simulation, OFFSETS};
+
+type NodeId = u32;
+
+const DEAD: NodeId = 0;
+const ALIVE: NodeId = 1;
+
+/// Node count above which `advance` collects garbage between jumps.
+pub const DEFAULT_NODE_LIMIT: usize = 1 << 22;
+
+/// A square of `2^level` cells split into `[nw, ne, sw, se]` quadrants.
+/// Level 0 nodes are single cells and have no children.
+#[derive(Clone, Copy)]
+struct Node {
+    children: [NodeId; 4],
+    level: u8,
+    population: u64,
+}
+
+/// Gosper's HashLife on the unbounded plane for two-state outer-totalistic
+/// rules. Identical squares are shared as one canonical quadtree node and the
+/// future of every node is memoized, so `advance` can jump through
+/// exponentially many generations of regular patterns.
+///
+/// The root node is centred on the origin and grows as the pattern does.
+pub struct HashLife {
+    rule: Rule,
+    nodes: Vec<Node>,
+    index: HashMap<[NodeId; 4], NodeId>,
+    /// Centre of a node advanced by `2^j` generations, keyed by node and `j`.
+    results: HashMap<(NodeId, u8), NodeId>,
+    empty: Vec<NodeId>,
+    root: NodeId,
+    generation: u64,
+    node_limit: usize,
+}
+
+impl HashLife {
+    pub fn new(rule: Rule) -> Result<Self, EngineError> {
+        let counted = matches!(rule.kind(), RuleKind::Totalistic | RuleKind::NonTotalistic);
+        if !counted || rule.states > 2 || rule.birth & 1 != 0 || rule.radius() > 1 {
+            return Err(EngineError::UnsupportedRule(rule.to_string()));
+        }
+        let leaf = |population| Node {
+            children: [DEAD; 4],
+            level: 0,
+            population,
+        };
+        let mut universe = HashLife {
+            rule,
+            nodes: vec![leaf(0), leaf(1)],
+            index: HashMap::new(),
+            results: HashMap::new(),
+            empty: vec![DEAD],
+            root: DEAD,
+            generation: 0,
+            node_limit: DEFAULT_NODE_LIMIT,
+        };
+        universe.root = universe.empty(3);
+        Ok(universe)
+    }
+
+    pub fn from_grid(grid: &Grid, rule: Rule) -> Result<Self, EngineError> {
+        let mut universe = HashLife::new(rule)?;
+        let cells: Vec<(i64, i64)> = grid
+            .iter()
+            .filter(|(_, &state)| state == 1)
+            .map(|(&(x, y), _)| (x as i64, y as i64))
+            .collect();
+        let extent = cells.iter().map(|&(x, y)| x.max(y).max(-x - 1).max(-y - 1)).max().unwrap_or(0);
+        let level = (65 - extent.leading_zeros() as u8).max(3);
+        let half = 1i64 << (level - 1);
+        let cells = cells.into_iter().map(|(x, y)| (x + half, y + half)).collect();
+        universe.root = universe.build(level, cells);
+        Ok(universe)
+    }
+
+    /// Sets how many nodes may accumulate before `advance` collects garbage.
+    pub fn with_node_limit(mut self, node_limit: usize) -> Self {
+        self.node_limit = node_limit;
+        self
+    }
+
+    /// Number of nodes currently held, live or not.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Moves the pattern `generations` generations forward, one power-of-two
+    /// jump per set bit.
+    pub fn advance(&mut self, generations: u64) {
+        for j in 0..64 {
+            if generations & (1 << j) != 0 {
+                self.advance_power_of_two(j);
+            }
+        }
+    }
+
+    fn advance_power_of_two(&mut self, j: u8) {
+        while self.level() < j + 2 || !self.is_padded() {
+            self.expand();
+        }
+        self.expand();
+        self.root = self.successor(self.root, j);
+        self.generation = self.generation.wrapping_add(1 << j);
+        if self.nodes.len() > self.node_limit {
+            self.collect_garbage();
+        }
+    }
+
+    /// Drops every node that is not part of the current pattern, along with
+    /// the memoized results that refer to them.
+    pub fn collect_garbage(&mut self) {
+        let mut marked = vec![false; self.nodes.len()];
+        marked[DEAD as usize] = true;
+        marked[ALIVE as usize] = true;
+        let mut stack: Vec<NodeId> = self.empty.clone();
+        stack.push(self.root);
+        while let Some(id) = stack.pop() {
+            if !marked[id as usize] {
+                marked[id as usize] = true;
+                stack.extend(self.nodes[id as usize].children);
+            }
+        }
+
+        // Children are always created before their parents, so compacting in
+        // order keeps every child id valid.
+        let mut remap = vec![DEAD; self.nodes.len()];
+        let mut nodes = Vec::with_capacity(marked.iter().filter(|&&m| m).count());
+        for (id, node) in self.nodes.iter().enumerate() {
+            if marked[id] {
+                remap[id] = nodes.len() as NodeId;
+                let mut node = *node;
+                if node.level > 0 {
+                    node.children = node.children.map(|child| remap[child as usize]);
+                }
+                nodes.push(node);
+            }
+        }
+
+        self.index = nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.level > 0)
+            .map(|(id, node)| (node.children, id as NodeId))
+            .collect();
+        self.results = self
+            .results
+            .iter()
+            .filter(|(&(node, _), &result)| marked[node as usize] && marked[result as usize])
+            .map(|(&(node, j), &result)| ((remap[node as usize], j), remap[result as usize]))
+            .collect();
+        self.empty = self.empty.iter().map(|&id| remap[id as usize]).collect();
+        self.root = remap[self.root as usize];
+        self.nodes = nodes;
+    }
+
+    fn level(&self) -> u8 {
+        self.nodes[self.root as usize].level
+    }
+
+    fn children(&self, id: NodeId) -> [NodeId; 4] {
+        self.nodes[id as usize].children
+    }
+
+    fn join(&mut self, children: [NodeId; 4]) -> NodeId {
+        if let Some(&id) = self.index.get(&children) {
+            return id;
+        }
+        let population = children
+            .iter()
+            .fold(0u64, |sum, &child| sum.saturating_add(self.nodes[child as usize].population));
+        let id = self.nodes.len() as NodeId;
+        self.nodes.push(Node {
+            children,
+            level: self.nodes[children[0] as usize].level + 1,
+            population,
+        });
+        self.index.insert(children, id);
+        id
+    }
+
+    fn empty(&mut self, level: u8) -> NodeId {
+        while self.empty.len() <= level as usize {
+            let below = *self.empty.last().unwrap();
+            let node = self.join([below; 4]);
+            self.empty.push(node);
+        }
+        self.empty[level as usize]
+    }
+
+    /// Builds a node of `level` from live cells given relative to its
+    /// top-left corner.
+    fn build(&mut self, level: u8, cells: Vec<(i64, i64)>) -> NodeId {
+        if cells.is_empty() {
+            return self.empty(level);
+        }
+        if level == 0 {
+            return ALIVE;
+        }
+        let half = 1i64 << (level - 1);
+        let mut quadrants: [Vec<(i64, i64)>; 4] = Default::default();
+        for (x, y) in cells {
+            let quadrant = (y >= half) as usize * 2 + (x >= half) as usize;
+            quadrants[quadrant].push((x % half, y % half));
+        }
+        let [nw, ne, sw, se] = quadrants;
+        let children = [
+            self.build(level - 1, nw),
+            self.build(level - 1, ne),
+            self.build(level - 1, sw),
+            self.build(level - 1, se),
+        ];
+        self.join(children)
+    }
+
+    /// Doubles the root around its centre, surrounding it with dead cells.
+    fn expand(&mut self) {
+        let e = self.empty(self.level() - 1);
+        let [nw, ne, sw, se] = self.children(self.root);
+        let children = [
+            self.join([e, e, e, nw]),
+            self.join([e, e, ne, e]),
+            self.join([e, sw, e, e]),
+            self.join([se, e, e, e]),
+        ];
+        self.root = self.join(children);
+    }
+
+    /// Whether every live cell lies in the central half of the root, so that
+    /// nothing can escape it within `2^(level - 2)` generations.
+    fn is_padded(&self) -> bool {
+        let [nw, ne, sw, se] = self.children(self.root);
+        let inner = [(nw, 3), (ne, 2), (sw, 1), (se, 0)]
+            .iter()
+            .map(|&(quadrant, inner)| self.nodes[self.children(quadrant)[inner] as usize].population)
+            .sum::<u64>();
+        inner == self.nodes[self.root as usize].population
+    }
+
+    fn centre(&mut self, id: NodeId) -> NodeId {
+        let [nw, ne, sw, se] = self.children(id);
+        self.join([self.children(nw)[3], self.children(ne)[2], self.children(sw)[1], self.children(se)[0]])
+    }
+
+    /// The nine overlapping half-size squares of a node, row by row.
+    fn ninths(&mut self, id: NodeId) -> [NodeId; 9] {
+        let [nw, ne, sw, se] = self.children(id);
+        let [_, nw_ne, nw_sw, nw_se] = self.children(nw);
+        let [ne_nw, _, ne_sw, ne_se] = self.children(ne);
+        let [sw_nw, sw_ne, _, sw_se] = self.children(sw);
+        let [se_nw, se_ne, se_sw, _] = self.children(se);
+        [
+            nw,
+            self.join([nw_ne, ne_nw, nw_se, ne_sw]),
+            ne,
+            self.join([nw_sw, nw_se, sw_nw, sw_ne]),
+            self.join([nw_se, ne_sw, sw_ne, se_nw]),
+            self.join([ne_sw, ne_se, se_nw, se_ne]),
+            sw,
+            self.join([sw_ne, se_nw, sw_se, se_sw]),
+            se,
+        ]
+    }
+
+    /// The centre half of a node advanced by `2^j` generations, where `j` is
+    /// at most the node's level minus two.
+    fn successor(&mut self, id: NodeId, j: u8) -> NodeId {
+        let node = self.nodes[id as usize];
+        if node.population == 0 {
+            return self.empty(node.level - 1);
+        }
+        if let Some(&result) = self.results.get(&(id, j)) {
+            return result;
+        }
+
+        let result = if node.level == 2 {
+            self.step_base(id)
+        } else {
+            let full_speed = j == node.level - 2;
+            let mut ninths = self.ninths(id);
+            for ninth in ninths.iter_mut() {
+                *ninth = if full_speed { self.successor(*ninth, j - 1) } else { self.centre(*ninth) };
+            }
+            let step = if full_speed { j - 1 } else { j };
+            let mut quarters = [DEAD; 4];
+            for (quarter, &corner) in quarters.iter_mut().zip([0, 1, 3, 4].iter()) {
+                let joined = self.join([ninths[corner], ninths[corner + 1], ninths[corner + 3], ninths[corner + 4]]);
+                *quarter = self.successor(joined, step);
+            }
+            self.join(quarters)
+        };
+        self.results.insert((id, j), result);
+        result
+    }
+
+    /// One generation of the centre 2x2 of a 4x4 node, looking at each cell's
+    /// neighbourhood directly.
+    fn step_base(&mut self, id: NodeId) -> NodeId {
+        let quadrants = self.children(id);
+        let cell = |x: usize, y: usize| self.children(quadrants[y / 2 * 2 + x / 2])[y % 2 * 2 + x % 2] as u8;
+        let next = |x: usize, y: usize| {
+            let neighbourhood = OFFSETS
+                .iter()
+                .filter(|&&(dx, dy)| cell((x as i32 + dx) as usize, (y as i32 + dy) as usize) == 1)
+                .fold(0, |neighbourhood, &(dx, dy)| neighbourhood | neighbour_bit(dx, dy));
+            self.rule.next_state_for(cell(x, y), neighbourhood) as NodeId
+        };
+        let children = [next(1, 1), next(2, 1), next(1, 2), next(2, 2)];
+        self.join(children)
+    }
+
+    /// Position relative to the root's top-left corner, if it lies inside.
+    fn locate(&self, x: i64, y: i64) -> Option<(i128, i128)> {
+        let size = 1i128 << self.level();
+        let (x, y) = (x as i128 + size / 2, y as i128 + size / 2);
+        if x >= 0 && x < size && y >= 0 && y < size {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    fn collect_cells(&self, id: NodeId, x: i128, y: i128, cells: &mut Vec<((i64, i64), u8)>) {
+        let node = self.nodes[id as usize];
+        if node.population == 0 {
+            return;
+        }
+        if node.level == 0 {
+            cells.push(((x as i64, y as i64), 1));
+            return;
+        }
+        let half = 1i128 << (node.level - 1);
+        for (quadrant, &child) in node.children.iter().enumerate() {
+            let (dx, dy) = ((quadrant % 2) as i128 * half, (quadrant / 2) as i128 * half);
+            self.collect_cells(child, x + dx, y + dy, cells);
+        }
+    }
+
+    fn set_in(&mut self, id: NodeId, x: i128, y: i128, state: u8) -> NodeId {
+        let level = self.nodes[id as usize].level;
+        if level == 0 {
+            return if state == 1 { ALIVE } else { DEAD };
+        }
+        let half = 1i128 << (level - 1);
+        let quadrant = (y >= half) as usize * 2 + (x >= half) as usize;
+        let mut children = self.children(id);
+        children[quadrant] = self.set_in(children[quadrant], x % half, y % half, state);
+        self.join(children)
+    }
+}
+
+impl Simulation for HashLife {
+    fn step(&mut self) {
+        self.advance(1);
+    }
+
+    fn step_n(&mut self, generations: u64) {
+        self.advance(generations);
+    }
+
+    fn get(&self, x: i64, y: i64) -> u8 {
+        let (mut x, mut y) = match self.locate(x, y) {
+            Some(pos) => pos,
+            None => return 0,
+        };
+        let mut id = self.root;
+        loop {
+            let node = self.nodes[id as usize];
+            if node.level == 0 || node.population == 0 {
+                return node.population as u8;
+            }
+            let half = 1i128 << (node.level - 1);
+            id = node.children[(y >= half) as usize * 2 + (x >= half) as usize];
+            x %= half;
+            y %= half;
+        }
+    }
+
+    fn set(&mut self, x: i64, y: i64, state: u8) {
+        let (x, y) = loop {
+            match self.locate(x, y) {
+                Some(pos) => break pos,
+                None => self.expand(),
+            }
+        };
+        self.root = self.set_in(self.root, x, y, state);
+    }
+
+    fn population(&self) -> usize {
+        self.nodes[self.root as usize].population as usize
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn cells(&self) -> Vec<((i64, i64), u8)> {
+        let mut cells = Vec::new();
+        let corner = -(1i128 << (self.level() - 1));
+        self.collect_cells(self.root, corner, corner, &mut cells);
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::parse_rule;
+    use crate::sparse::SparseUniverse;
+
+    const GOSPER_GUN: [&str; 9] = [
+        "........................O...........",
+        "......................O.O...........",
+        "............OO......OO............OO",
+        "...........O...O....OO............OO",
+        "OO........O.....O...OO..............",
+        "OO........O...O.OO....O.O...........",
+        "..........O.....O.......O...........",
+        "...........O...O....................",
+        "............OO......................",
+    ];
+
+    fn grid_from(rows: &[&str]) -> Grid {
+        let mut grid = Grid::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                grid.insert((x as i32, y as i32), (c == 'O') as i32);
+            }
+        }
+        grid
+    }
+
+    fn assert_matches_sparse(grid: &Grid, rule: Rule, generations: u64) {
+        let mut hashlife = HashLife::from_grid(grid, rule).unwrap();
+        let mut sparse = SparseUniverse::from_grid(grid, rule);
+        for _ in 0..generations {
+            hashlife.step();
+            sparse.step();
+            assert_eq!(hashlife.population(), sparse.population(), "{} generation {}", rule, sparse.generation());
+            let mut cells = hashlife.cells();
+            let mut expected = sparse.cells();
+            cells.sort_unstable();
+            expected.sort_unstable();
+            assert_eq!(cells, expected);
+        }
+    }
+
+    #[test]
+    fn test_single_steps_match_sparse_universe() {
+        let r_pentomino = grid_from(&[".OO", "OO.", ".O."]);
+        assert_matches_sparse(&r_pentomino, Rule::conway(), 200);
+        assert_matches_sparse(&r_pentomino, parse_rule("B36/S23").unwrap(), 100);
+        assert_matches_sparse(&grid_from(&GOSPER_GUN), Rule::conway(), 100);
+        assert_matches_sparse(&r_pentomino, parse_rule("B3/S2-i34q").unwrap(), 100);
+        assert_matches_sparse(&r_pentomino, parse_rule("B2-a/S12").unwrap(), 50);
+        let north_west = parse_rule(&format!("MAP{}D{}w", "A".repeat(42), "/".repeat(42))).unwrap();
+        assert_matches_sparse(&r_pentomino, north_west, 20);
+    }
+
+    #[test]
+    fn test_jumps_match_single_steps() {
+        let r_pentomino = grid_from(&[".OO", "OO.", ".O."]);
+        let mut jumping = HashLife::from_grid(&r_pentomino, Rule::conway()).unwrap();
+        let mut sparse = SparseUniverse::from_grid(&r_pentomino, Rule::conway());
+        jumping.advance(1103);
+        sparse.step_n(1103);
+
+        assert_eq!(jumping.generation(), 1103);
+        assert_eq!(jumping.population(), 116);
+        for ((x, y), _) in sparse.cells() {
+            assert_eq!(jumping.get(x, y), 1);
+        }
+    }
+
+    #[test]
+    fn test_glider_after_a_trillion_generations() {
+        let glider = grid_from(&[".O.", "..O", "OOO"]);
+        let mut universe = HashLife::from_grid(&glider, Rule::conway()).unwrap();
+        universe.advance(1_000_000_000_000);
+
+        assert_eq!(universe.population(), 5);
+        let shift = 250_000_000_000;
+        assert_eq!(universe.get(1 + shift, shift), 1);
+        assert_eq!(universe.get(2 + shift, 1 + shift), 1);
+        assert_eq!(universe.get(1 + shift, 1 + shift), 0);
+    }
+
+    #[test]
+    fn test_gosper_gun_after_a_trillion_generations() {
+        let mut universe = HashLife::from_grid(&grid_from(&GOSPER_GUN), Rule::conway()).unwrap();
+        universe.advance(1_000_000_000_000);
+
+        // The gun emits a glider every 30 generations and each of them
+        // stays intact on its way out.
+        let gliders = universe.population() as i64 / 5;
+        assert!((gliders - 1_000_000_000_000 / 30).abs() < 10);
+    }
+
+    #[test]
+    fn test_garbage_collection_keeps_the_pattern() {
+        let gun = grid_from(&GOSPER_GUN);
+        let mut collected = HashLife::from_grid(&gun, Rule::conway()).unwrap().with_node_limit(1000);
+        let mut reference = HashLife::from_grid(&gun, Rule::conway()).unwrap();
+        for _ in 0..20 {
+            collected.advance(97);
+            reference.advance(97);
+            assert_eq!(collected.population(), reference.population());
+        }
+        collected.collect_garbage();
+        assert!(collected.node_count() < reference.node_count());
+        collected.advance(1000);
+        reference.advance(1000);
+        assert_eq!(collected.population(), reference.population());
+    }
+
+    #[test]
+    fn test_set_and_get_grow_the_root() {
+        let mut universe = HashLife::new(Rule::conway()).unwrap();
+        universe.set(-5_000_000_000, 7, 1);
+        universe.set(3, 4_000_000_000_000, 1);
+        assert_eq!(universe.get(-5_000_000_000, 7), 1);
+        assert_eq!(universe.get(3, 4_000_000_000_000), 1);
+        assert_eq!(universe.get(0, 0), 0);
+        assert_eq!(universe.population(), 2);
+
+        universe.step();
+        assert_eq!(universe.population(), 0);
+    }
+
+    #[test]
+    fn test_rejects_unsupported_rules() {
+        for rule in ["B2/S/C3", "B0/S8", "R5,C0,M1,S34..58,B34..45,NM"] {
+            assert_eq!(
+                HashLife::new(parse_rule(rule).unwrap()).err(),
+                Some(EngineError::UnsupportedRule(rule.to_string()))
+            );
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use crate::resources::{neighbour_bit, RuleParseError, Transitions, CENTRE};
+
+/// The letters each neighbour count can take, in the order they are
+/// written.
+const LETTERS: [&str; 9] = ["", "ce", "cekain", "cekainyqjr", "cekainyqjrtwz", "cekainyqjr", "cekain", "ce", ""];
+
+/// Every letter used by any count.
+pub const ALPHABET: &str = "cekainyqjrtwz";
+
+const N: u16 = neighbour_bit(0, -1);
+const NE: u16 = neighbour_bit(1, -1);
+const E: u16 = neighbour_bit(1, 0);
+const SE: u16 = neighbour_bit(1, 1);
+const S: u16 = neighbour_bit(0, 1);
+const SW: u16 = neighbour_bit(-1, 1);
+const W: u16 = neighbour_bit(-1, 0);
+const NW: u16 = neighbour_bit(-1, -1);
+
+/// One configuration of each letter for counts 1 to 4. Counts 5 to 7 use
+/// the complement of the same letter for `8 - count`.
+const REPRESENTATIVES: [&[(char, u16)]; 4] = [
+    &[('c', NE), ('e', N)],
+    &[('c', NE | SE), ('e', N | E), ('k', N | SE), ('a', N | NE), ('i', N | S), ('n', NE | SW)],
+    &[
+        ('c', NE | SE | SW),
+        ('e', N | E | W),
+        ('k', N | E | SW),
+        ('a', N | NE | E),
+        ('i', NW | N | NE),
+        ('n', NW | NE | E),
+        ('y', N | SE | SW),
+        ('q', NW | N | SE),
+        ('j', NW | N | E),
+        ('r', NE | E | W),
+    ],
+    &[
+        ('c', NE | SE | SW | NW),
+        ('e', N | E | S | W),
+        ('k', N | NE | SE | W),
+        ('a', N | NE | E | NW),
+        ('i', N | NE | SE | S),
+        ('n', N | NE | SE | NW),
+        ('y', N | NE | SE | SW),
+        ('q', N | NE | E | SW),
+        ('j', N | NE | S | W),
+        ('r', N | NE | E | W),
+        ('t', N | NE | S | NW),
+        ('w', N | E | SE | NW),
+        ('z', N | NE | S | SW),
+    ],
+];
+
+/// The eight neighbours, without the centre.
+const RING: u16 = 0x1ff & !CENTRE;
+
+/// All the neighbourhoods, without the centre, that `count` and `letter`
+/// stand for: a configuration and its rotations and reflections.
+fn class(count: usize, letter: char) -> Vec<u16> {
+    let representative = match count {
+        1..=4 => REPRESENTATIVES[count - 1].iter().find(|&&(l, _)| l == letter).map(|&(_, mask)| mask),
+        5..=7 => class(8 - count, letter).first().map(|&mask| RING & !mask),
+        _ => None,
+    };
+    let mut masks = Vec::new();
+    let mut mask = match representative {
+        Some(mask) => mask,
+        None => return masks,
+    };
+    for _ in 0..4 {
+        for candidate in [mask, transform(mask, |dx, dy| (-dx, dy))] {
+            if !masks.contains(&candidate) {
+                masks.push(candidate);
+            }
+        }
+        mask = transform(mask, |dx, dy| (-dy, dx));
+    }
+    masks
+}
+
+fn transform(mask: u16, f: impl Fn(i32, i32) -> (i32, i32)) -> u16 {
+    let mut out = 0;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            if mask & neighbour_bit(dx, dy) != 0 {
+                let (tx, ty) = f(dx, dy);
+                out |= neighbour_bit(tx, ty);
+            }
+        }
+    }
+    out
+}
+
+/// All neighbourhoods, without the centre, with `count` live neighbours.
+fn with_count(count: usize) -> impl Iterator<Item = u16> {
+    (0..=RING).filter(move |&mask| mask & CENTRE == 0 && mask.count_ones() as usize == count)
+}
+
+/// Adds the neighbourhoods of a B or S section such as `2-a` or `23-a4ei`
+/// to `table`, with the centre set when `centre` is. Each digit takes every
+/// configuration of its count, only the listed letters, or with a `-` every
+/// letter but the listed ones.
+pub fn parse_section(section: &str, centre: bool, table: &mut Transitions) -> Result<(), RuleParseError> {
+    let centre = if centre { CENTRE } else { 0 };
+    let mut chars = section.chars().peekable();
+    while let Some(digit) = chars.next() {
+        let count = match digit.to_digit(10) {
+            Some(count) if count <= 8 => count as usize,
+            Some(_) => return Err(RuleParseError::CountOutOfRange(digit)),
+            None => return Err(RuleParseError::UnexpectedCharacter(digit)),
+        };
+        let negated = chars.next_if_eq(&'-').is_some();
+        let mut group = format!("{}{}", digit, if negated { "-" } else { "" });
+        let mut letters = String::new();
+        while let Some(letter) = chars.next_if(|c| !c.is_ascii_digit()) {
+            group.push(letter);
+            if !LETTERS[count].contains(letter) || letters.contains(letter) {
+                return Err(RuleParseError::InvalidNeighbourhood(group));
+            }
+            letters.push(letter);
+        }
+        if negated && letters.is_empty() {
+            return Err(RuleParseError::InvalidNeighbourhood(group));
+        }
+
+        let wanted = |letter: char| letters.is_empty() || letters.contains(letter) != negated;
+        if LETTERS[count].is_empty() {
+            with_count(count).for_each(|mask| table.insert(mask | centre));
+        }
+        for letter in LETTERS[count].chars().filter(|&letter| wanted(letter)) {
+            class(count, letter).into_iter().for_each(|mask| table.insert(mask | centre));
+        }
+    }
+    Ok(())
+}
+
+/// Tells whether every rotation and reflection of each neighbourhood in
+/// `table` leads to the same state, so that it can be written with letters.
+pub fn is_isotropic(table: &Transitions) -> bool {
+    (0..0x200u16).all(|index| {
+        let on = table.contains(index);
+        let centre = index & CENTRE;
+        let mut mask = index & !CENTRE;
+        (0..4).all(|_| {
+            let same = table.contains(mask | centre) == on && table.contains(transform(mask, |dx, dy| (-dx, dy)) | centre) == on;
+            mask = transform(mask, |dx, dy| (-dy, dx));
+            same
+        })
+    })
+}
+
+/// The B or S section of `table` for cells with the centre as given, in
+/// the shortest of the listed and `-` forms.
+pub fn write_section(table: &Transitions, centre: bool) -> String {
+    let centre = if centre { CENTRE } else { 0 };
+    let mut out = String::new();
+    for (count, letters) in LETTERS.iter().enumerate() {
+        if letters.is_empty() {
+            if with_count(count).all(|mask| table.contains(mask | centre)) {
+                out.push_str(&count.to_string());
+            }
+            continue;
+        }
+        let (present, missing): (String, String) =
+            letters.chars().partition(|&letter| table.contains(class(count, letter)[0] | centre));
+        if present.is_empty() {
+            continue;
+        }
+        out.push_str(&count.to_string());
+        if missing.is_empty() {
+            continue;
+        }
+        if missing.len() < present.len() {
+            out.push('-');
+            out.push_str(&missing);
+        } else {
+            out.push_str(&present);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_letters_split_each_count_into_its_classes() {
+        for (count, letters) in LETTERS.iter().enumerate().take(8).skip(1) {
+            let mut seen = Vec::new();
+            for letter in letters.chars() {
+                let masks = class(count, letter);
+                assert!(masks.iter().all(|mask| mask.count_ones() as usize == count), "{}{}", count, letter);
+                assert!(masks.iter().all(|mask| !seen.contains(mask)), "{}{}", count, letter);
+                seen.extend(masks);
+            }
+            assert_eq!(seen.len(), with_count(count).count(), "{}", count);
+        }
+    }
+
+    #[test]
+    fn test_named_configurations() {
+        assert!(class(2, 'i').contains(&(W | E)));
+        assert!(class(2, 'a').contains(&(NW | W)));
+        assert!(class(3, 'y').contains(&(NW | NE | S)));
+        assert!(class(4, 't').contains(&(NW | N | NE | S)));
+        assert_eq!(class(4, 'c'), vec![NE | SE | SW | NW]);
+        let mut seven = class(7, 'e');
+        seven.sort_unstable();
+        assert_eq!(seven, vec![RING & !N, RING & !W, RING & !E, RING & !S]);
+    }
+
+    #[test]
+    fn test_three_and_five_letters_match_golly() {
+        for (letter, mask) in [
+            ('c', NE | SE | SW),
+            ('e', N | E | W),
+            ('k', N | E | SW),
+            ('a', N | NE | E),
+            ('i', NW | N | NE),
+            ('n', NW | NE | E),
+            ('y', N | SE | SW),
+            ('q', NW | N | SE),
+            ('j', NW | N | E),
+            ('r', NE | E | W),
+        ] {
+            assert!(class(3, letter).contains(&mask), "3{}", letter);
+            assert!(class(5, letter).contains(&(RING & !mask)), "5{}", letter);
+        }
+        // The L of a corner and its two edges is 3a, the straight line 3i.
+        assert!(class(3, 'a').contains(&(NW | N | W)));
+        assert!(class(3, 'i').contains(&(NE | E | SE)));
+    }
+
+    #[test]
+    fn test_is_isotropic() {
+        let mut table = Transitions::default();
+        parse_section("2-a", false, &mut table).unwrap();
+        assert!(is_isotropic(&table));
+        table.insert(N);
+        assert!(!is_isotropic(&table));
+    }
+
+    #[test]
+    fn test_write_section_prefers_the_shorter_form() {
+        let mut table = Transitions::default();
+        parse_section("2-a3ce4cekainyqjrtwz", false, &mut table).unwrap();
+        assert_eq!(write_section(&table, false), "2-a3ce4");
+        assert_eq!(write_section(&table, true), "");
+    }
+}
@@ -1,7 +1,24 @@
-pub mod alternative_rules;
+pub mod apgcode;
+pub mod bitgrid;
 pub mod components;
+pub mod composition;
+pub mod hashlife;
+pub mod hensel;
+pub mod library;
+pub mod life;
+pub mod ltl;
+pub mod macrocell;
+pub mod map;
+pub mod named_rules;
+pub mod neighbourhood;
+pub mod pattern;
+pub mod plaintext;
 pub mod resources;
+pub mod rle;
 pub mod seeder;
+pub mod sparse;
 pub mod systems;
+pub mod topology;
+pub mod universe;
 
 pub use crate::seeder::Seeder;
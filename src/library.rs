@@ -0,0 +1,253 @@
+use crate::pattern::Pattern;
+use crate::resources::parse_rule;
+use crate::rle;
+
+/// A well-known pattern built into the binary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub rule: &'static str,
+    /// The cells as an RLE body, without the `x = .., y = ..` header.
+    pub body: &'static str,
+}
+
+impl Entry {
+    /// The entry's cells, named and described, with its rule.
+    pub fn pattern(&self) -> Pattern {
+        let mut pattern = rle::parse_body(self.body).expect("library patterns are valid RLE");
+        pattern.name = Some(self.name.to_string());
+        pattern.comments = vec![self.description.to_string()];
+        pattern.rule = Some(parse_rule(self.rule).expect("library rules are valid"));
+        pattern
+    }
+}
+
+pub const PATTERNS: &[Entry] = &[
+    Entry {
+        name: "glider",
+        description: "The smallest spaceship, moving diagonally at c/4.",
+        rule: "B3/S23",
+        body: "bo$2bo$3o!",
+    },
+    Entry {
+        name: "lwss",
+        description: "Lightweight spaceship, moving orthogonally at c/2.",
+        rule: "B3/S23",
+        body: "bo2bo$o4b$o3bo$4o!",
+    },
+    Entry {
+        name: "mwss",
+        description: "Middleweight spaceship, moving orthogonally at c/2.",
+        rule: "B3/S23",
+        body: "3bo2b$bo3bo$o5b$o4bo$5o!",
+    },
+    Entry {
+        name: "hwss",
+        description: "Heavyweight spaceship, moving orthogonally at c/2.",
+        rule: "B3/S23",
+        body: "3b2o2b$bo4bo$o6b$o5bo$6o!",
+    },
+    Entry {
+        name: "copperhead",
+        description: "The first c/10 orthogonal spaceship, found in 2016.",
+        rule: "B3/S23",
+        body: "b2o2b2o$3b2o$3b2o$obo2bobo$o6bo2$o6bo$b2o2b2o$2b4o2$3b2o$3b2o!",
+    },
+    Entry {
+        name: "blinker",
+        description: "The smallest and most common oscillator, period 2.",
+        rule: "B3/S23",
+        body: "3o!",
+    },
+    Entry {
+        name: "toad",
+        description: "A period 2 oscillator of two offset rows.",
+        rule: "B3/S23",
+        body: "b3o$3o!",
+    },
+    Entry {
+        name: "beacon",
+        description: "A period 2 oscillator of two diagonal blocks.",
+        rule: "B3/S23",
+        body: "2o$2o$2b2o$2b2o!",
+    },
+    Entry {
+        name: "pulsar",
+        description: "The most common period 3 oscillator.",
+        rule: "B3/S23",
+        body: "2b3o3b3o2b2$o4bobo4bo$o4bobo4bo$o4bobo4bo$2b3o3b3o2b2$2b3o3b3o2b$o4bobo4bo$o4bobo4bo$o4bobo4bo2$2b3o3b3o!",
+    },
+    Entry {
+        name: "pentadecathlon",
+        description: "A period 15 oscillator grown from a row of ten cells.",
+        rule: "B3/S23",
+        body: "2bo4bo2b$2ob4ob2o$2bo4bo!",
+    },
+    Entry {
+        name: "r-pentomino",
+        description: "A methuselah that settles after 1103 generations.",
+        rule: "B3/S23",
+        body: "b2o$2o$bo!",
+    },
+    Entry {
+        name: "acorn",
+        description: "A methuselah that takes 5206 generations to settle.",
+        rule: "B3/S23",
+        body: "bo5b$3bo3b$2o2b3o!",
+    },
+    Entry {
+        name: "diehard",
+        description: "A methuselah that vanishes after 130 generations.",
+        rule: "B3/S23",
+        body: "6bob$2o6b$bo3b3o!",
+    },
+    Entry {
+        name: "gosper-glider-gun",
+        description: "The first known gun, firing a glider every 30 generations.",
+        rule: "B3/S23",
+        body: "24bo$22bobo$12b2o6b2o12b2o$11bo3bo4b2o12b2o$2o8bo5bo3b2o$2o8bo3bob2o4bobo$10bo5bo7bo$11bo3bo$12b2o!",
+    },
+    Entry {
+        name: "simkin-glider-gun",
+        description: "The smallest known gun, firing a glider every 120 generations.",
+        rule: "B3/S23",
+        body: "2o5b2o$2o5b2o2$4b2o$4b2o5$22b2ob2o$21bo5bo$21bo6bo2b2o$21b3o3bo3b2o$26bo4$20b2o$20bo$21b3o$23bo!",
+    },
+    Entry {
+        name: "replicator",
+        description: "HighLife's replicator, which copies itself every 12 generations.",
+        rule: "B36/S23",
+        body: "2b3o$bo2bo$o3bo$o2bo$3o!",
+    },
+    Entry {
+        name: "langtons-loop",
+        description: "Langton's loop, which builds a copy of itself in 151 generations.",
+        rule: "Langtons-Loops",
+        body: ".8B$BAG.AD.ADB$B.6B.B$BGB4.BAB$BAB4.BAB$B.B4.BAB$BGB4.BAB$BA6BA5B$B.GA.GA.G5AB$.13B!",
+    },
+    Entry {
+        name: "electron-loop",
+        description: "A Wireworld electron running around a ring of wire every 10 generations.",
+        rule: "WireWorld",
+        body: ".4C$C4.C$.BA2C!",
+    },
+];
+
+/// The entry called `name`, ignoring case, spaces, `-` and `_`.
+pub fn find(name: &str) -> Option<&'static Entry> {
+    let key = |name: &str| -> String {
+        name.chars().filter(|c| !matches!(c, ' ' | '-' | '_')).flat_map(char::to_lowercase).collect()
+    };
+    let wanted = key(name);
+    PATTERNS.iter().find(|entry| key(entry.name) == wanted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apgcode;
+    use crate::sparse::SparseUniverse;
+    use crate::universe::Simulation;
+
+    fn universe(name: &str) -> SparseUniverse {
+        let pattern = find(name).unwrap().pattern();
+        SparseUniverse::from_grid(&pattern.cells, pattern.rule.unwrap())
+    }
+
+    fn population_at(universe: &mut SparseUniverse, generation: u64) -> usize {
+        universe.step_n(generation - universe.generation());
+        universe.population()
+    }
+
+    #[test]
+    fn test_every_entry_parses() {
+        for entry in PATTERNS {
+            let pattern = entry.pattern();
+            assert!(!pattern.cells.is_empty(), "{}", entry.name);
+            assert_eq!(pattern.name.as_deref(), Some(entry.name));
+            assert_eq!(find(entry.name), Some(entry));
+        }
+    }
+
+    #[test]
+    fn test_find_ignores_case_and_separators() {
+        assert_eq!(find("Gosper Glider Gun").map(|entry| entry.name), Some("gosper-glider-gun"));
+        assert_eq!(find("R_PENTOMINO").map(|entry| entry.name), Some("r-pentomino"));
+        assert_eq!(find("LWSS").map(|entry| entry.name), Some("lwss"));
+        assert_eq!(find("unicorn"), None);
+    }
+
+    #[test]
+    fn test_objects_have_their_apgcodes() {
+        let objects = [
+            ("glider", "xq4_153"),
+            ("lwss", "xq4_6frc"),
+            ("mwss", "xq4_27dee6"),
+            ("hwss", "xq4_27deee6"),
+            ("blinker", "xp2_7"),
+            ("toad", "xp2_7e"),
+            ("beacon", "xp2_318c"),
+            ("pentadecathlon", "xp15_4r4z4r4"),
+        ];
+        for (name, code) in objects {
+            let pattern = find(name).unwrap().pattern();
+            assert_eq!(apgcode::encode(&pattern, pattern.rule.unwrap()).as_deref(), Ok(code), "{}", name);
+        }
+        let copperhead = find("copperhead").unwrap().pattern();
+        assert!(apgcode::encode(&copperhead, copperhead.rule.unwrap()).unwrap().starts_with("xq10_"));
+        let pulsar = find("pulsar").unwrap().pattern();
+        assert!(apgcode::encode(&pulsar, pulsar.rule.unwrap()).unwrap().starts_with("xp3_"));
+    }
+
+    #[test]
+    fn test_methuselahs() {
+        let mut diehard = universe("diehard");
+        assert!(population_at(&mut diehard, 129) > 0);
+        assert_eq!(population_at(&mut diehard, 130), 0);
+
+        let mut r_pentomino = universe("r-pentomino");
+        // 116 cells, counting the six gliders that escape.
+        assert_eq!(population_at(&mut r_pentomino, 1103), 116);
+    }
+
+    #[test]
+    fn test_guns_fire_gliders() {
+        let mut gosper = universe("gosper-glider-gun");
+        let before = population_at(&mut gosper, 120);
+        assert_eq!(population_at(&mut gosper, 150), before + 5);
+
+        let mut simkin = universe("simkin-glider-gun");
+        let before = population_at(&mut simkin, 480);
+        assert_eq!(population_at(&mut simkin, 600), before + 5);
+    }
+
+    #[test]
+    fn test_langtons_loop_builds_a_copy() {
+        let mut loop_universe = universe("langtons-loop");
+        let start = loop_universe.cells().len();
+        loop_universe.step_n(151);
+        // The parent and the daughter share one wall.
+        assert_eq!(loop_universe.cells().len(), 2 * start - 1);
+    }
+
+    #[test]
+    fn test_electron_loop_returns_every_10_generations() {
+        let mut electron = universe("electron-loop");
+        let mut start = electron.cells();
+        start.sort_unstable();
+        for generation in 1..=10 {
+            electron.step();
+            let mut cells = electron.cells();
+            cells.sort_unstable();
+            assert_eq!(cells == start, generation == 10, "generation {}", generation);
+        }
+    }
+
+    #[test]
+    fn test_replicator_copies_itself() {
+        let mut replicator = universe("replicator");
+        let start = replicator.population();
+        assert_eq!(population_at(&mut replicator, 12), 2 * start);
+    }
+}
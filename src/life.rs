@@ -0,0 +1,184 @@
+//! The Life 1.05 and Life 1.06 formats used by older pattern collections.
+
+use std::fmt::Write;
+
+use crate::neighbourhood::Neighbourhood;
+use crate::pattern::{Pattern, PatternError};
+use crate::resources::{parse_rule, Rule, RuleKind};
+
+pub const HEADER_105: &str = "#Life 1.05";
+pub const HEADER_106: &str = "#Life 1.06";
+
+/// Parses Life 1.05: `#D` description lines, `#N` for Conway's rule or
+/// `#R` with an S/B rule, then `#P x y` blocks of `.` and `*` rows placed
+/// with their top-left corner at `x`, `y`.
+pub fn parse_105(text: &str) -> Result<Pattern, PatternError> {
+    let mut cells = Vec::new();
+    let mut comments = Vec::new();
+    let mut rule = None;
+    let (mut left, mut y) = (0, 0);
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with(HEADER_105) {
+            continue;
+        }
+        if let Some(directive) = line.strip_prefix('#') {
+            let mut chars = directive.chars();
+            let kind = chars.next();
+            let rest = chars.as_str().trim();
+            match kind {
+                Some('D' | 'C') => comments.push(rest.to_string()),
+                Some('N') => rule = Some(Rule::conway()),
+                Some('R') => rule = Some(parse_rule(rest).map_err(PatternError::InvalidRule)?),
+                Some('P') => {
+                    let invalid = || PatternError::InvalidHeader(line.to_string());
+                    let mut offsets = rest.split_whitespace().map(|n| n.parse::<i64>().map_err(|_| invalid()));
+                    left = offsets.next().ok_or_else(invalid)??;
+                    y = offsets.next().ok_or_else(invalid)??;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                '.' => {}
+                '*' | 'O' => cells.push(((left + x as i64, y), 1)),
+                _ => return Err(PatternError::UnexpectedCharacter(c)),
+            }
+        }
+        y += 1;
+    }
+
+    let mut pattern = Pattern::from_cells(cells.into_iter());
+    pattern.comments = comments;
+    pattern.rule = rule;
+    Ok(pattern)
+}
+
+/// Writes a two-state pattern as a single Life 1.05 block. The name goes
+/// out as the first `#D` line, so it reads back as a comment.
+pub fn write_105(pattern: &Pattern) -> Result<String, PatternError> {
+    pattern.check_two_state()?;
+    let mut out = format!("{}\n", HEADER_105);
+    for comment in pattern.name.iter().chain(&pattern.comments) {
+        writeln!(out, "#D {}", comment).unwrap();
+    }
+    match pattern.rule {
+        Some(rule) if rule.kind() == RuleKind::Named => return Err(PatternError::UnsupportedRule(rule.to_string())),
+        Some(rule) if rule == Rule::conway() => out.push_str("#N\n"),
+        Some(rule) if rule.states > 2 => return Err(PatternError::MultiState(rule.states as i32)),
+        Some(rule) if rule.kind() != RuleKind::Totalistic || rule.neighbourhood != Neighbourhood::default() => {
+            return Err(PatternError::UnsupportedRule(rule.to_string()))
+        }
+        Some(rule) => writeln!(out, "#R {}/{}", digits(rule.survive), digits(rule.birth)).unwrap(),
+        None => {}
+    }
+    writeln!(out, "#P {} {}", -(pattern.width / 2), -(pattern.height / 2)).unwrap();
+    for y in 0..pattern.height {
+        let row: String = (0..pattern.width)
+            .map(|x| if pattern.cells.contains_key(&(x, y)) { '*' } else { '.' })
+            .collect();
+        writeln!(out, "{}", row.trim_end_matches('.')).unwrap();
+    }
+    Ok(out)
+}
+
+fn digits(counts: u64) -> String {
+    (0..=8).filter(|n| counts & (1 << n) != 0).map(|n| n.to_string()).collect()
+}
+
+/// Parses Life 1.06: the header followed by one `x y` pair per live cell.
+pub fn parse_106(text: &str) -> Result<Pattern, PatternError> {
+    let mut cells = Vec::new();
+    for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let invalid = || PatternError::InvalidHeader(line.to_string());
+        let mut coordinates = line.split_whitespace().map(|n| n.parse::<i64>().map_err(|_| invalid()));
+        let x = coordinates.next().ok_or_else(invalid)??;
+        let y = coordinates.next().ok_or_else(invalid)??;
+        cells.push(((x, y), 1));
+    }
+    Ok(Pattern::from_cells(cells.into_iter()))
+}
+
+/// Writes a two-state pattern as Life 1.06 coordinates. The format has no
+/// room for a name, comments or a rule.
+pub fn write_106(pattern: &Pattern) -> Result<String, PatternError> {
+    pattern.check_two_state()?;
+    let mut out = format!("{}\n", HEADER_106);
+    let mut cells: Vec<&(i32, i32)> = pattern.cells.keys().collect();
+    cells.sort_by_key(|&&(x, y)| (y, x));
+    for (x, y) in cells {
+        writeln!(out, "{} {}", x, y).unwrap();
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER_105: &str = "#Life 1.05
+#D Glider
+#R 23/36
+#P -1 -1
+.*
+..*
+***
+";
+
+    #[test]
+    fn test_parse_105() {
+        let pattern = parse_105(GLIDER_105).unwrap();
+        assert_eq!(pattern.comments, vec!["Glider"]);
+        assert_eq!(pattern.rule, Some(parse_rule("B36/S23").unwrap()));
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.cells.keys().copied().collect::<Vec<_>>(), vec![(0, 2), (1, 0), (1, 2), (2, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_parse_105_blocks() {
+        let pattern = parse_105("#Life 1.05\n#N\n#P -10 0\n**\n#P 5 -2\n*\n").unwrap();
+        assert_eq!(pattern.rule, Some(Rule::conway()));
+        assert_eq!((pattern.width, pattern.height), (16, 3));
+        assert_eq!(pattern.cells.keys().copied().collect::<Vec<_>>(), vec![(0, 2), (1, 2), (15, 0)]);
+    }
+
+    #[test]
+    fn test_write_105_round_trips() {
+        let pattern = parse_105(GLIDER_105).unwrap();
+        assert_eq!(write_105(&pattern).unwrap(), GLIDER_105);
+    }
+
+    #[test]
+    fn test_write_105_reads_the_name_back_as_a_comment() {
+        let mut pattern = parse_105(GLIDER_105).unwrap();
+        pattern.name = Some("Glider".to_string());
+        pattern.comments = vec!["Found in 1970".to_string()];
+        let read = parse_105(&write_105(&pattern).unwrap()).unwrap();
+        assert_eq!(read.name, None);
+        assert_eq!(read.comments, vec!["Glider", "Found in 1970"]);
+        assert_eq!(read.cells, pattern.cells);
+    }
+
+    #[test]
+    fn test_parse_and_write_106() {
+        let text = "#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1\n";
+        let pattern = parse_106(text).unwrap();
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.cells.keys().copied().collect::<Vec<_>>(), vec![(0, 2), (1, 0), (1, 2), (2, 1), (2, 2)]);
+        assert_eq!(write_106(&pattern).unwrap(), "#Life 1.06\n1 0\n2 1\n0 2\n1 2\n2 2\n");
+        assert_eq!(parse_106(&write_106(&pattern).unwrap()).unwrap(), pattern);
+        assert_eq!(parse_106("#Life 1.06\n1 x\n"), Err(PatternError::InvalidHeader("1 x".to_string())));
+    }
+
+    #[test]
+    fn test_write_rejects_multi_state() {
+        let mut pattern = parse_106("1 1").unwrap();
+        pattern.rule = Some(parse_rule("B2/S/C3").unwrap());
+        assert_eq!(write_105(&pattern), Err(PatternError::MultiState(3)));
+        pattern.rule = Some(parse_rule("B2-a/S12").unwrap());
+        assert_eq!(write_105(&pattern), Err(PatternError::UnsupportedRule("B2-a/S12".to_string())));
+        pattern.rule = Some(parse_rule("WireWorld").unwrap());
+        assert_eq!(write_105(&pattern), Err(PatternError::UnsupportedRule("WireWorld".to_string())));
+    }
+}
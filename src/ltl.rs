@@ -0,0 +1,292 @@
+use crate::resources::RuleParseError;
+
+/// Largest radius of a Larger than Life neighbourhood.
+pub const MAX_RADIUS: u8 = 10;
+
+/// The shape of a Larger than Life neighbourhood.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    /// The square of cells within the radius in both directions, `NM`.
+    Moore,
+    /// The diamond of cells within the radius steps along the axes, `NN`.
+    VonNeumann,
+    /// The cells within half a cell more than the radius of the centre,
+    /// `NC`.
+    Circular,
+}
+
+impl Shape {
+    fn letter(&self) -> char {
+        match self {
+            Shape::Moore => 'M',
+            Shape::VonNeumann => 'N',
+            Shape::Circular => 'C',
+        }
+    }
+
+    /// How far the row `dy` rows from the centre reaches either side.
+    fn half_width(&self, radius: i32, dy: i32) -> i32 {
+        match self {
+            Shape::Moore => radius,
+            Shape::VonNeumann => radius - dy.abs(),
+            Shape::Circular => (0..=radius).rev().find(|dx| dx * dx + dy * dy <= radius * radius + radius).unwrap_or(0),
+        }
+    }
+}
+
+/// A Larger than Life rule: a cell is born or survives when the live count
+/// of its neighbourhood, which reaches up to `radius` cells away, lies in
+/// the inclusive `birth` or `survive` range. With `middle` set the cell
+/// counts itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Ltl {
+    pub radius: u8,
+    pub middle: bool,
+    pub survive: (u16, u16),
+    pub birth: (u16, u16),
+    pub shape: Shape,
+}
+
+impl Ltl {
+    pub fn born(&self, count: i32) -> bool {
+        (self.birth.0 as i32..=self.birth.1 as i32).contains(&count)
+    }
+
+    pub fn survives(&self, count: i32) -> bool {
+        (self.survive.0 as i32..=self.survive.1 as i32).contains(&count)
+    }
+
+    /// The offsets of the counted cells, row by row, the centre included
+    /// when `middle` is set.
+    pub fn offsets(&self) -> Vec<(i32, i32)> {
+        let radius = self.radius as i32;
+        (-radius..=radius)
+            .flat_map(|dy| {
+                let reach = self.shape.half_width(radius, dy);
+                (-reach..=reach).map(move |dx| (dx, dy))
+            })
+            .filter(|&offset| self.middle || offset != (0, 0))
+            .collect()
+    }
+
+    /// The largest live count.
+    pub fn size(&self) -> usize {
+        self.offsets().len()
+    }
+
+    /// The neighbourhood, centre included, as `(top, bottom, half_width)`
+    /// rectangles of rows around the cell that reach as far either side.
+    fn rectangles(&self) -> Vec<(i32, i32, i32)> {
+        let radius = self.radius as i32;
+        let mut rectangles: Vec<(i32, i32, i32)> = Vec::new();
+        for dy in -radius..=radius {
+            let half_width = self.shape.half_width(radius, dy);
+            match rectangles.last_mut() {
+                Some((_, bottom, width)) if *width == half_width => *bottom = dy,
+                _ => rectangles.push((dy, dy, half_width)),
+            }
+        }
+        rectangles
+    }
+}
+
+/// A summed-area table of the live cells in a region, which finds the
+/// live count of an LtL neighbourhood with four lookups per rectangle of
+/// rows instead of one per cell.
+pub struct SummedArea {
+    left: i64,
+    top: i64,
+    width: usize,
+    /// Live cells above and to the left of each position, with an extra
+    /// row and column of zeros at the top and left.
+    sums: Vec<u32>,
+    rectangles: Vec<(i32, i32, i32)>,
+    middle: bool,
+}
+
+impl SummedArea {
+    /// Sums the `width` by `height` region with its top-left corner at
+    /// `(left, top)`, reading cells with `live`.
+    pub fn new(ltl: &Ltl, (left, top): (i64, i64), width: usize, height: usize, live: impl Fn(i64, i64) -> bool) -> Self {
+        let stride = width + 1;
+        let mut sums = vec![0; stride * (height + 1)];
+        for y in 0..height {
+            let mut row = 0;
+            for x in 0..width {
+                row += live(left + x as i64, top + y as i64) as u32;
+                sums[(y + 1) * stride + x + 1] = sums[y * stride + x + 1] + row;
+            }
+        }
+        SummedArea {
+            left,
+            top,
+            width: stride,
+            sums,
+            rectangles: ltl.rectangles(),
+            middle: ltl.middle,
+        }
+    }
+
+    /// Live cells in the rectangle between two corners, both included.
+    fn rectangle(&self, (x0, y0): (i64, i64), (x1, y1): (i64, i64)) -> u32 {
+        let at = |x: i64, y: i64| self.sums[(y - self.top) as usize * self.width + (x - self.left) as usize];
+        at(x1 + 1, y1 + 1) + at(x0, y0) - at(x0, y1 + 1) - at(x1 + 1, y0)
+    }
+
+    /// The live count of the neighbourhood of `(x, y)`, which must lie in
+    /// the region with all its neighbours.
+    pub fn count(&self, x: i64, y: i64) -> i32 {
+        let total: u32 = self
+            .rectangles
+            .iter()
+            .map(|&(top, bottom, half_width)| {
+                let half_width = half_width as i64;
+                self.rectangle((x - half_width, y + top as i64), (x + half_width, y + bottom as i64))
+            })
+            .sum();
+        let centre = if self.middle { 0 } else { self.rectangle((x, y), (x, y)) };
+        (total - centre) as i32
+    }
+}
+
+/// Parses the fields of a rule such as `R5,C0,M1,S34..58,B34..45,NM`, in
+/// any case and order: the radius, the state count (0 and 1 mean two
+/// states), whether the cell counts itself, the survival and birth ranges
+/// and the neighbourhood shape. `C`, `M` and `N` default to `C0`, `M0` and
+/// `NM`. Returns the rule and its state count.
+pub fn parse(rule_str: &str) -> Result<(Ltl, u8), RuleParseError> {
+    let mut fields: [Option<&str>; 6] = [None; 6];
+    for field in rule_str.split(',').map(str::trim) {
+        let key = field.chars().next().ok_or(RuleParseError::UnexpectedCharacter(','))?;
+        let slot = match "RCMSBN".find(key.to_ascii_uppercase()) {
+            Some(slot) => slot,
+            None => return Err(RuleParseError::UnexpectedCharacter(key)),
+        };
+        if fields[slot].replace(field).is_some() {
+            return Err(RuleParseError::DuplicateSection(key.to_ascii_uppercase()));
+        }
+    }
+    let [radius, states, middle, survive, birth, shape] = fields;
+
+    let radius = radius.ok_or(RuleParseError::MissingSection('R'))?;
+    let radius = match radius[1..].parse::<u8>() {
+        Ok(value) if (1..=MAX_RADIUS).contains(&value) => value,
+        _ => return Err(RuleParseError::InvalidRadius(radius[1..].to_string())),
+    };
+    let states = match states {
+        Some(states) => states[1..].parse::<u8>().map_err(|_| RuleParseError::InvalidStateCount(states[1..].to_string()))?,
+        None => 0,
+    };
+    let middle = match middle.map(|middle| &middle[1..]) {
+        None | Some("0") => false,
+        Some("1") => true,
+        Some(_) => return Err(RuleParseError::InvalidField(middle.unwrap_or_default().to_string())),
+    };
+    let shape = match shape.map(|shape| shape[1..].to_ascii_uppercase()).as_deref() {
+        None | Some("M") => Shape::Moore,
+        Some("N") => Shape::VonNeumann,
+        Some("C") => Shape::Circular,
+        Some(_) => return Err(RuleParseError::InvalidNeighbourhoodShape(shape.unwrap_or_default().to_string())),
+    };
+    let mut ltl = Ltl {
+        radius,
+        middle,
+        survive: (0, 0),
+        birth: (0, 0),
+        shape,
+    };
+    ltl.survive = parse_range(survive.ok_or(RuleParseError::MissingSection('S'))?, ltl.size())?;
+    ltl.birth = parse_range(birth.ok_or(RuleParseError::MissingSection('B'))?, ltl.size())?;
+    Ok((ltl, states.max(2)))
+}
+
+/// Parses an `S` or `B` field such as `S34..58`, whose counts run upwards
+/// and can be at most `size`.
+fn parse_range(field: &str, size: usize) -> Result<(u16, u16), RuleParseError> {
+    let invalid = || RuleParseError::InvalidRange(field.to_string());
+    let (low, high) = field[1..].split_once("..").ok_or_else(invalid)?;
+    match (low.parse::<u16>(), high.parse::<u16>()) {
+        (Ok(low), Ok(high)) if low <= high && high as usize <= size => Ok((low, high)),
+        _ => Err(invalid()),
+    }
+}
+
+/// The rule in the form `parse` reads, with `C0` for two states.
+pub fn write(ltl: &Ltl, states: u8) -> String {
+    format!(
+        "R{},C{},M{},S{}..{},B{}..{},N{}",
+        ltl.radius,
+        if states > 2 { states } else { 0 },
+        ltl.middle as u8,
+        ltl.survive.0,
+        ltl.survive.1,
+        ltl.birth.0,
+        ltl.birth.1,
+        ltl.shape.letter()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bosco() -> Ltl {
+        parse("R5,C0,M1,S34..58,B34..45,NM").unwrap().0
+    }
+
+    #[test]
+    fn test_parse_and_write() {
+        assert_eq!(
+            bosco(),
+            Ltl {
+                radius: 5,
+                middle: true,
+                survive: (34, 58),
+                birth: (34, 45),
+                shape: Shape::Moore,
+            }
+        );
+        assert_eq!(write(&bosco(), 2), "R5,C0,M1,S34..58,B34..45,NM");
+        let (ltl, states) = parse("r2,c3,s1..2,b3..3,nc").unwrap();
+        assert_eq!((ltl.middle, ltl.shape, states), (false, Shape::Circular, 3));
+        assert_eq!(write(&ltl, states), "R2,C3,M0,S1..2,B3..3,NC");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("R11,S1..2,B3..3"), Err(RuleParseError::InvalidRadius("11".to_string())));
+        assert_eq!(parse("R1,M5,S1..2,B3..3"), Err(RuleParseError::InvalidField("M5".to_string())));
+        assert_eq!(parse("R1,S9..3,B3..3"), Err(RuleParseError::InvalidRange("S9..3".to_string())));
+        assert_eq!(parse("R1,S1..2,B3..3,NX"), Err(RuleParseError::InvalidNeighbourhoodShape("NX".to_string())));
+        assert_eq!(parse("R1,S1..2"), Err(RuleParseError::MissingSection('B')));
+        assert_eq!(parse("R1,S1..2,B3..3,s2..2"), Err(RuleParseError::DuplicateSection('S')));
+        assert_eq!(parse("R1,S1..9,B3..3"), Err(RuleParseError::InvalidRange("S1..9".to_string())));
+        assert_eq!(parse("R1,S1-2,B3..3"), Err(RuleParseError::InvalidRange("S1-2".to_string())));
+        assert_eq!(parse("R1,S1..2,B3..3,X"), Err(RuleParseError::UnexpectedCharacter('X')));
+    }
+
+    #[test]
+    fn test_shapes() {
+        let sizes = |radius, shape| Ltl { radius, middle: false, survive: (0, 0), birth: (0, 0), shape }.size();
+        assert_eq!(sizes(5, Shape::Moore), 120);
+        assert_eq!(sizes(2, Shape::VonNeumann), 12);
+        // Radius 2 reaches (2, 1) but not (2, 2).
+        assert_eq!(sizes(2, Shape::Circular), 20);
+        assert_eq!(bosco().size(), 121);
+    }
+
+    #[test]
+    fn test_summed_area_matches_counting_each_cell() {
+        let live = |x: i64, y: i64| (x * 7 + y * 13) % 5 < 2;
+        for shape in [Shape::Moore, Shape::VonNeumann, Shape::Circular] {
+            for middle in [false, true] {
+                let ltl = Ltl { radius: 3, middle, survive: (0, 0), birth: (0, 0), shape };
+                let area = SummedArea::new(&ltl, (-5, -5), 20, 20, live);
+                for (x, y) in [(-2, -2), (0, 0), (4, 7), (11, 11)] {
+                    let expected = ltl.offsets().iter().filter(|&&(dx, dy)| live(x + dx as i64, y + dy as i64)).count();
+                    assert_eq!(area.count(x, y), expected as i32, "{:?} {} ({}, {})", shape, middle, x, y);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use crate::pattern::{Pattern, PatternError};
+use crate::resources::{parse_rule_spec, Dimensions, Rule};
+use crate::topology::BoundedGrid;
+
+pub const HEADER: &str = "[M2]";
+
+/// Deepest tree `Macrocell` accepts, so that every cell position fits an
+/// `i64`.
+const MAX_LEVEL: u8 = 62;
+
+/// A quadtree node of a macrocell file. Children are node numbers, with 0
+/// standing for an empty square.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Node {
+    Empty,
+    /// An 8x8 two-state square, one byte per row with bit `x` for column `x`.
+    Leaf8([u8; 8]),
+    /// A 2x2 multi-state square as `[nw, ne, sw, se]` states.
+    Leaf2([u8; 4]),
+    Inner { level: u8, children: [usize; 4] },
+}
+
+impl Node {
+    fn level(&self) -> u8 {
+        match self {
+            Node::Empty => 0,
+            Node::Leaf8(_) => 3,
+            Node::Leaf2(_) => 1,
+            Node::Inner { level, .. } => *level,
+        }
+    }
+}
+
+/// A pattern in Golly's `[M2]` macrocell format, kept as the quadtree it
+/// describes so that huge patterns can be inspected without flattening.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Macrocell {
+    pub rule: Option<Rule>,
+    pub bounded_grid: Option<BoundedGrid>,
+    pub generation: Option<u64>,
+    pub comments: Vec<String>,
+    nodes: Vec<Node>,
+}
+
+impl Macrocell {
+    fn root(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    /// Side length of the square the tree covers, as a power of two.
+    pub fn level(&self) -> u8 {
+        self.nodes[self.root()].level()
+    }
+
+    /// Smallest `(min_x, min_y, max_x, max_y)` box holding every non-dead
+    /// cell, with the root centred on the origin as Golly places it.
+    pub fn bounding_box(&self) -> Option<(i64, i64, i64, i64)> {
+        let mut boxes = vec![None; self.nodes.len()];
+        for (index, node) in self.nodes.iter().enumerate() {
+            boxes[index] = match *node {
+                Node::Empty => None,
+                Node::Leaf8(rows) => {
+                    let live = (0..64).filter(|bit| rows[bit / 8] & (1 << (bit % 8)) != 0);
+                    cells_box(live.map(|bit| (bit % 8, bit / 8)))
+                }
+                Node::Leaf2(states) => cells_box((0..4).filter(|&i| states[i] != 0).map(|i| (i % 2, i / 2))),
+                Node::Inner { level, children } => {
+                    let half = 1i64 << (level - 1);
+                    children
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(quadrant, &child)| {
+                            let (dx, dy) = ((quadrant % 2) as i64 * half, (quadrant / 2) as i64 * half);
+                            boxes[child].map(|(x0, y0, x1, y1)| (x0 + dx, y0 + dy, x1 + dx, y1 + dy))
+                        })
+                        .reduce(union)
+                }
+            };
+        }
+        let corner = -(1i64 << self.level()) / 2;
+        boxes[self.root()].map(|(x0, y0, x1, y1)| (x0 + corner, y0 + corner, x1 + corner, y1 + corner))
+    }
+
+    /// Flattens the tree into a `Pattern`, failing when the pattern is
+    /// bigger than `fit`.
+    pub fn to_pattern(&self, fit: Option<Dimensions>) -> Result<Pattern, PatternError> {
+        if let (Some(fit), Some((x0, y0, x1, y1))) = (fit, self.bounding_box()) {
+            let (width, height) = (x1 - x0 + 1, y1 - y0 + 1);
+            if width > fit.width as i64 || height > fit.height as i64 {
+                return Err(PatternError::TooLarge(width, height));
+            }
+        }
+        let mut cells = Vec::new();
+        let corner = -(1i64 << self.level()) / 2;
+        self.collect_cells(self.root(), corner, corner, &mut cells);
+        let mut pattern = Pattern::from_cells(cells.into_iter());
+        pattern.rule = self.rule;
+        pattern.bounded_grid = self.bounded_grid;
+        pattern.comments = self.comments.clone();
+        Ok(pattern)
+    }
+
+    fn collect_cells(&self, index: usize, x: i64, y: i64, cells: &mut Vec<((i64, i64), u8)>) {
+        match self.nodes[index] {
+            Node::Empty => {}
+            Node::Leaf8(rows) => {
+                for (dy, row) in rows.iter().enumerate() {
+                    for dx in (0..8).filter(|dx| row & (1 << dx) != 0) {
+                        cells.push(((x + dx as i64, y + dy as i64), 1));
+                    }
+                }
+            }
+            Node::Leaf2(states) => {
+                for (i, &state) in states.iter().enumerate().filter(|(_, &state)| state != 0) {
+                    cells.push(((x + (i % 2) as i64, y + (i / 2) as i64), state));
+                }
+            }
+            Node::Inner { level, children } => {
+                let half = 1i64 << (level - 1);
+                for (quadrant, &child) in children.iter().enumerate() {
+                    self.collect_cells(child, x + (quadrant % 2) as i64 * half, y + (quadrant / 2) as i64 * half, cells);
+                }
+            }
+        }
+    }
+}
+
+fn cells_box(cells: impl Iterator<Item = (usize, usize)>) -> Option<(i64, i64, i64, i64)> {
+    cells.map(|(x, y)| (x as i64, y as i64, x as i64, y as i64)).reduce(union)
+}
+
+fn union(a: (i64, i64, i64, i64), b: (i64, i64, i64, i64)) -> (i64, i64, i64, i64) {
+    (a.0.min(b.0), a.1.min(b.1), a.2.max(b.2), a.3.max(b.3))
+}
+
+/// Parses an `[M2]` macrocell file. `#R` gives the rule, `#G` the
+/// generation and `#C` or `#N` lines comments. Each following line is a
+/// node: an 8x8 two-state leaf of `.`, `*` and `$`, a `1 a b c d` multi-state
+/// leaf, or `level nw ne sw se` referring to earlier lines by number. The
+/// last node is the root.
+pub fn parse(text: &str) -> Result<Macrocell, PatternError> {
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+    match lines.next() {
+        Some(line) if line.starts_with(HEADER) => {}
+        _ => return Err(PatternError::MissingHeader),
+    }
+
+    let mut macrocell = Macrocell {
+        rule: None,
+        bounded_grid: None,
+        generation: None,
+        comments: Vec::new(),
+        nodes: vec![Node::Empty],
+    };
+    for line in lines {
+        let invalid = || PatternError::InvalidNode(line.to_string());
+        if let Some(directive) = line.strip_prefix('#') {
+            let mut chars = directive.chars();
+            let kind = chars.next();
+            let rest = chars.as_str().trim();
+            match kind {
+                Some('R') => {
+                    let (rule, bounded_grid) = parse_rule_spec(rest).map_err(PatternError::InvalidRule)?;
+                    macrocell.rule = Some(rule);
+                    macrocell.bounded_grid = bounded_grid;
+                }
+                Some('G') => macrocell.generation = Some(rest.parse().map_err(|_| invalid())?),
+                Some('C' | 'N') => macrocell.comments.push(rest.to_string()),
+                _ => {}
+            }
+            continue;
+        }
+
+        let node = if line.starts_with(['.', '*', '$']) {
+            parse_leaf8(line)?
+        } else {
+            let numbers = line
+                .split_whitespace()
+                .map(|n| n.parse::<usize>().map_err(|_| invalid()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let (level, children) = match numbers[..] {
+                [level, a, b, c, d] => (level, [a, b, c, d]),
+                _ => return Err(invalid()),
+            };
+            if level == 1 {
+                let mut states = [0; 4];
+                for (state, &child) in states.iter_mut().zip(&children) {
+                    *state = u8::try_from(child).map_err(|_| invalid())?;
+                }
+                Node::Leaf2(states)
+            } else {
+                let children_fit = children.iter().all(|&child| {
+                    child < macrocell.nodes.len() && (child == 0 || macrocell.nodes[child].level() + 1 == level as u8)
+                });
+                if level < 2 || level > MAX_LEVEL as usize || !children_fit {
+                    return Err(invalid());
+                }
+                Node::Inner { level: level as u8, children }
+            }
+        };
+        macrocell.nodes.push(node);
+    }
+    if macrocell.nodes.len() == 1 {
+        return Err(PatternError::InvalidNode(String::new()));
+    }
+    Ok(macrocell)
+}
+
+fn parse_leaf8(line: &str) -> Result<Node, PatternError> {
+    let mut rows = [0u8; 8];
+    let (mut x, mut y) = (0, 0);
+    for c in line.chars() {
+        match c {
+            '$' => {
+                x = 0;
+                y += 1;
+                continue;
+            }
+            '.' => {}
+            '*' if x < 8 && y < 8 => rows[y] |= 1 << x,
+            '*' => return Err(PatternError::InvalidNode(line.to_string())),
+            _ => return Err(PatternError::UnexpectedCharacter(c)),
+        }
+        x += 1;
+    }
+    Ok(Node::Leaf8(rows))
+}
+
+/// Writes a pattern as an `[M2]` macrocell file, sharing identical squares
+/// as one node. Patterns with states above 1 or a multi-state rule get
+/// `1 a b c d` leaves, the rest 8x8 leaves. The tree grows to hold every
+/// cell.
+pub fn write(pattern: &Pattern) -> String {
+    let pattern = &pattern.boxed();
+    let multi_state = pattern.rule.is_some_and(|rule| rule.states > 2) || pattern.cells.values().any(|&state| state > 1);
+    let side = pattern.width.max(pattern.height).max(1) as u64;
+    let leaf_level = if multi_state { 1 } else { 3 };
+    let level = (64 - (side - 1).leading_zeros() as u8).max(leaf_level + 1);
+
+    let mut builder = TreeBuilder {
+        multi_state,
+        nodes: vec![Node::Empty],
+        index: HashMap::new(),
+    };
+    let cells = pattern.cells.iter().map(|(&(x, y), &state)| ((x as i64, y as i64), state as u8)).collect();
+    let root = builder.build(level, cells);
+    if root == 0 {
+        builder.nodes.push(Node::Inner { level, children: [0; 4] });
+    }
+
+    let mut out = format!("{} (bedelli)\n", HEADER);
+    if let Some(rule) = &pattern.rule {
+        write!(out, "#R {}", rule).unwrap();
+        if let Some(grid) = &pattern.bounded_grid {
+            write!(out, ":{}", grid).unwrap();
+        }
+        out.push('\n');
+    }
+    for comment in pattern.name.iter().chain(&pattern.comments) {
+        writeln!(out, "#C {}", comment).unwrap();
+    }
+    for node in &builder.nodes[1..] {
+        match node {
+            Node::Empty => {}
+            Node::Leaf8(rows) => {
+                let used = rows.iter().rposition(|&row| row != 0).map_or(0, |last| last + 1);
+                for row in &rows[..used] {
+                    let width = 8 - row.leading_zeros() as usize;
+                    out.extend((0..width).map(|x| if row & (1 << x) != 0 { '*' } else { '.' }));
+                    out.push('$');
+                }
+                out.push('\n');
+            }
+            Node::Leaf2([a, b, c, d]) => writeln!(out, "1 {} {} {} {}", a, b, c, d).unwrap(),
+            Node::Inner { level, children: [a, b, c, d] } => writeln!(out, "{} {} {} {} {}", level, a, b, c, d).unwrap(),
+        }
+    }
+    out
+}
+
+struct TreeBuilder {
+    multi_state: bool,
+    nodes: Vec<Node>,
+    index: HashMap<Node, usize>,
+}
+
+impl TreeBuilder {
+    /// Node number of the square of `level` holding `cells`, given relative
+    /// to its top-left corner, or 0 when it is empty.
+    fn build(&mut self, level: u8, cells: Vec<((i64, i64), u8)>) -> usize {
+        if cells.is_empty() {
+            return 0;
+        }
+        let node = if level == 1 && self.multi_state {
+            let mut states = [0; 4];
+            for ((x, y), state) in cells {
+                states[(y * 2 + x) as usize] = state;
+            }
+            Node::Leaf2(states)
+        } else if level == 3 && !self.multi_state {
+            let mut rows = [0; 8];
+            for ((x, y), _) in cells {
+                rows[y as usize] |= 1 << x;
+            }
+            Node::Leaf8(rows)
+        } else {
+            let half = 1i64 << (level - 1);
+            let mut quadrants: [Vec<((i64, i64), u8)>; 4] = Default::default();
+            for ((x, y), state) in cells {
+                quadrants[(y >= half) as usize * 2 + (x >= half) as usize].push(((x % half, y % half), state));
+            }
+            let [nw, ne, sw, se] = quadrants;
+            let children = [
+                self.build(level - 1, nw),
+                self.build(level - 1, ne),
+                self.build(level - 1, sw),
+                self.build(level - 1, se),
+            ];
+            Node::Inner { level, children }
+        };
+        let nodes = &mut self.nodes;
+        *self.index.entry(node).or_insert_with(|| {
+            nodes.push(node);
+            nodes.len() - 1
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::parse_rule;
+    use crate::rle;
+
+    const GLIDER: &str = "[M2] (golly 4.2)
+#R B3/S23
+#G 12
+#C A glider
+$$$$$..*$...*$.***$
+4 0 0 0 1
+";
+
+    #[test]
+    fn test_parse_two_state() {
+        let macrocell = parse(GLIDER).unwrap();
+        assert_eq!(macrocell.rule, Some(Rule::conway()));
+        assert_eq!(macrocell.generation, Some(12));
+        assert_eq!(macrocell.comments, vec!["A glider"]);
+        assert_eq!(macrocell.level(), 4);
+        assert_eq!(macrocell.bounding_box(), Some((1, 5, 3, 7)));
+
+        let pattern = macrocell.to_pattern(None).unwrap();
+        let glider = rle::parse("x = 3, y = 3\nbo$2bo$3o!").unwrap();
+        assert_eq!(pattern.cells, glider.cells);
+    }
+
+    #[test]
+    fn test_parse_multi_state() {
+        let text = "[M2]\n#R B2/S/C3\n1 0 1 2 0\n1 1 0 0 0\n2 1 0 2 1\n3 0 3 0 0\n";
+        let macrocell = parse(text).unwrap();
+        assert_eq!(macrocell.rule, Some(parse_rule("B2/S/C3").unwrap()));
+        let pattern = macrocell.to_pattern(None).unwrap();
+        assert_eq!(pattern.cells.len(), 5);
+        assert_eq!((pattern.width, pattern.height), (4, 4));
+        assert_eq!(pattern.cells.get(&(0, 1)), Some(&2));
+        assert_eq!(pattern.cells.get(&(2, 3)), Some(&2));
+    }
+
+    #[test]
+    fn test_shared_nodes_flatten_every_copy() {
+        let text = "[M2]\n*$\n4 1 1 1 1\n5 2 2 2 2\n";
+        let pattern = parse(text).unwrap().to_pattern(None).unwrap();
+        assert_eq!(pattern.cells.len(), 16);
+        assert_eq!((pattern.width, pattern.height), (25, 25));
+    }
+
+    #[test]
+    fn test_to_pattern_checks_the_board_size() {
+        let macrocell = parse("[M2]\n*$\n4 1 0 0 1\n").unwrap();
+        assert!(macrocell.to_pattern(Some(Dimensions { width: 9, height: 9 })).is_ok());
+        assert_eq!(
+            macrocell.to_pattern(Some(Dimensions { width: 8, height: 20 })),
+            Err(PatternError::TooLarge(9, 9))
+        );
+    }
+
+    #[test]
+    fn test_write_round_trips() {
+        let sources = [
+            "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!",
+            "x = 40, y = 21, rule = B36/S23:T80,50\n3o5b4o$$4bo$12o$$o16$39bo!",
+            "x = 5, y = 2, rule = B2/S/C3\n.A2B$pApX!",
+        ];
+        for source in sources {
+            let pattern = rle::parse(source).unwrap();
+            let written = write(&pattern);
+            let read = parse(&written).unwrap().to_pattern(None).unwrap();
+            assert_eq!(read.cells, pattern.cells, "{}", written);
+            assert_eq!(read.rule, pattern.rule);
+            assert_eq!(read.bounded_grid, pattern.bounded_grid);
+        }
+    }
+
+    #[test]
+    fn test_write_shares_identical_squares() {
+        let mut pattern = Pattern { width: 64, height: 64, ..Pattern::default() };
+        for x in (0..64).step_by(8) {
+            for y in (0..64).step_by(8) {
+                pattern.cells.insert((x, y), 1);
+            }
+        }
+        let written = write(&pattern);
+        assert_eq!(written.lines().count(), 5);
+        assert_eq!(parse(&written).unwrap().to_pattern(None).unwrap().cells, pattern.cells);
+    }
+
+    #[test]
+    fn test_write_grows_the_tree_to_the_cells() {
+        let mut pattern = Pattern { width: 2, height: 2, ..Pattern::default() };
+        pattern.cells.extend([((-3, -2), 1), ((0, 0), 1), ((5, 20), 1)]);
+        let read = parse(&write(&pattern)).unwrap().to_pattern(None).unwrap();
+        assert_eq!(read.cells, pattern.boxed().cells);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("x = 1, y = 1\no!"), Err(PatternError::MissingHeader));
+        assert_eq!(parse("[M2]\n4 0 0 0 7\n"), Err(PatternError::InvalidNode("4 0 0 0 7".to_string())));
+        assert_eq!(parse("[M2]\n*$\n5 1 0 0 0\n"), Err(PatternError::InvalidNode("5 1 0 0 0".to_string())));
+        assert_eq!(parse("[M2]\n*x$\n"), Err(PatternError::UnexpectedCharacter('x')));
+    }
+}
@@ -1,4 +1,6 @@
+use std::collections::HashMap;
 use std::env;
+use std::fs;
 
 use bracket_lib::prelude::*;
 use legion::*;
@@ -6,44 +8,60 @@ use legion::*;
 use bedelli::components::*;
 use bedelli::resources::*;
 use bedelli::systems::*;
+use bedelli::seeder::Soup;
 use bedelli::Seeder;
 
-use bedelli::resources::{parse_rule, RuleFn, InputState, PositionIndex};
+use bedelli::resources::{parse_rule, parse_rule_spec, Engine, InputState, Topology, Viewport};
+use bedelli::apgcode;
+use bedelli::composition::{self, Composition};
+use bedelli::library;
+use bedelli::pattern::{self, Format, Pattern, PatternError, Placement, Region};
+use bedelli::topology::{parse_topology, BoundedGrid};
+use bedelli::universe::{Backend, Simulation};
+
+/// Where the S and W keys save the board, and what to record about where it
+/// came from.
+struct SaveSettings {
+    path: String,
+    source: String,
+    bounded_grid: Option<BoundedGrid>,
+}
 
 struct GameState {
     ecs: World,
     resources: Resources,
     schedule: Schedule,
+    scrollable: bool,
+    save_settings: SaveSettings,
 }
 
 impl GameState {
-    fn new(width: i32, height: i32, seeder: &Seeder, rule: RuleFn) -> Self {
+    /// Shows `engine` through a `width` x `height` window. A scrollable
+    /// window is a viewport that the arrow keys move around the plane.
+    fn new(width: i32, height: i32, engine: Box<dyn Simulation>, scrollable: bool, save_settings: SaveSettings) -> Self {
         let mut ecs = World::default();
         let mut resources = Resources::default();
 
-        let grid = seeder.seed(width, height);
-
-        let mut position_index = std::collections::HashMap::new();
-        for ((x, y), state) in grid {
-            let entity = ecs.push((
+        for (x, y) in (0..width).flat_map(|x| (0..height).map(move |y| (x, y))) {
+            let state = engine.get(x as i64, y as i64);
+            ecs.push((
                 Position { x, y },
-                Cell { alive: state == 1 },
+                Cell { state },
+                NextCell { state },
                 Age::default(),
                 CellColor::default(),
             ));
-            position_index.insert((x, y), entity);
         }
 
         resources.insert(Dimensions { width, height });
-        resources.insert(rule);
-        resources.insert(PositionIndex(position_index));
+        resources.insert(Engine(engine));
+        resources.insert(Viewport::default());
         resources.insert(InputState::default());
 
         let schedule = Schedule::builder()
             .add_system(mouse_toggle_system())
             .add_system(neighbor_counting_system())
             .add_system(state_update_system())
-            .add_system(cleanup_next_system())
             .add_system(age_update_system())
             .add_system(color_update_system())
             .build();
@@ -52,6 +70,27 @@ impl GameState {
             ecs,
             resources,
             schedule,
+            scrollable,
+            save_settings,
+        }
+    }
+
+    /// Writes the live cells inside `region`, or all of them, to the save
+    /// path.
+    fn save(&self, region: Option<Region>) {
+        let engine = self.resources.get::<Engine>().unwrap();
+        let mut pattern = Pattern::capture(engine.0.as_ref(), region);
+        pattern.bounded_grid = self.save_settings.bounded_grid;
+        pattern.comments.push(format!("Generation {}", engine.0.generation()));
+        pattern.comments.push(format!("Source: {}", self.save_settings.source));
+        let path = &self.save_settings.path;
+        let written = Format::from_path(path)
+            .write(&pattern)
+            .map_err(|e| e.to_string())
+            .and_then(|text| fs::write(path, text).map_err(|e| e.to_string()));
+        match written {
+            Ok(()) => println!("saved generation {} to {}", engine.0.generation(), path),
+            Err(e) => eprintln!("could not save {}: {}", path, e),
         }
     }
 }
@@ -59,12 +98,31 @@ impl GameState {
 impl bracket_lib::prelude::GameState for GameState {
     fn tick(&mut self, ctx: &mut BTerm) {
         match ctx.key {
-            Some(VirtualKeyCode::Escape) => {
-                ctx.quit();
+            Some(VirtualKeyCode::Escape) => ctx.quit(),
+            Some(VirtualKeyCode::S) => self.save(None),
+            Some(VirtualKeyCode::W) => {
+                let viewport = *self.resources.get::<Viewport>().unwrap();
+                let dimensions = *self.resources.get::<Dimensions>().unwrap();
+                self.save(Some(Region {
+                    x: viewport.x,
+                    y: viewport.y,
+                    width: dimensions.width as i64,
+                    height: dimensions.height as i64,
+                }));
             }
             _ => {}
         }
 
+        if let (true, Some(mut viewport)) = (self.scrollable, self.resources.get_mut::<Viewport>()) {
+            match ctx.key {
+                Some(VirtualKeyCode::Left) => viewport.x -= 10,
+                Some(VirtualKeyCode::Right) => viewport.x += 10,
+                Some(VirtualKeyCode::Up) => viewport.y -= 10,
+                Some(VirtualKeyCode::Down) => viewport.y += 10,
+                _ => {}
+            }
+        }
+
         let mouse_pos = ctx.mouse_point();
         let mouse_left = ctx.left_click;
 
@@ -78,23 +136,154 @@ impl bracket_lib::prelude::GameState for GameState {
     }
 }
 
+/// The random soup the `--seed`, `--density`, `--soup` and `--symmetry`
+/// options describe. Without `--seed` one is picked and printed so the board
+/// can be replayed.
+fn soup(options: &HashMap<String, String>) -> Result<Soup, Box<dyn std::error::Error + Send + Sync>> {
+    let seed = match options.get("seed") {
+        Some(seed) => seed.parse()?,
+        None => {
+            let seed = rand::random();
+            println!("soup seed {}", seed);
+            seed
+        }
+    };
+    let mut soup = Soup::new(seed);
+    if let Some(density) = options.get("density") {
+        soup = soup.with_density(density.parse()?)?;
+    }
+    if let Some(size) = options.get("soup") {
+        soup = soup.with_size(size.parse()?);
+    }
+    if let Some(symmetry) = options.get("symmetry") {
+        soup = soup.with_symmetry(symmetry.parse()?);
+    }
+    Ok(soup)
+}
+
+/// A built-in pattern, an apgcode or a pattern file, which must fit in `fit`.
+fn load_source(source: &str, fit: Option<Dimensions>) -> Result<Pattern, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(match library::find(source) {
+        Some(entry) => entry.pattern(),
+        None if apgcode::is_apgcode(source) => apgcode::decode(source)?,
+        None => pattern::load(source, fit)?,
+    })
+}
+
 fn main() -> BError {
     // Force X11 backend on Linux to avoid Wayland compositor performance issues
     #[cfg(target_os = "linux")]
     env::remove_var("WAYLAND_DISPLAY");
 
-    let args: Vec<String> = env::args().collect();
+    let mut args = Vec::new();
+    let mut options = HashMap::new();
+    let mut raw_args = env::args();
+    while let Some(arg) = raw_args.next() {
+        match arg.strip_prefix("--") {
+            Some(name) => {
+                let value = raw_args.next().ok_or(format!("option --{} needs a value", name))?;
+                options.insert(name.to_string(), value);
+            }
+            None => args.push(arg),
+        }
+    }
+    if args.get(1).is_some_and(|arg| arg == "patterns") {
+        for entry in library::PATTERNS {
+            println!("{:<20} {:<8} {}", entry.name, entry.rule, entry.description);
+        }
+        return Ok(());
+    }
+    if let (Some("map"), Some(rule)) = (args.get(1).map(String::as_str), args.get(2)) {
+        let rule = parse_rule(rule)?;
+        println!("{}", rule.to_map().ok_or(format!("a MAP string cannot hold {}", rule))?);
+        return Ok(());
+    }
     if args.len() < 5 {
         panic!("at least four arguments required! width, height, rule and initial board type");
     }
     let width = args[1].parse::<i32>().unwrap();
     let height = args[2].parse::<i32>().unwrap();
     let rulestr = &args[3];
-    let seeder_type = args[4].parse::<u32>().unwrap();
+    let backend = match options.get("engine") {
+        Some(name) => name.parse::<Backend>()?,
+        None => Backend::default(),
+    };
+    let threads = match options.get("threads") {
+        Some(threads) => threads.parse::<usize>()?,
+        None => 0,
+    };
 
-    let rule = parse_rule(&rulestr);
-    let seeder = Seeder::new(seeder_type);
-    let game_state = GameState::new(width, height, &seeder, rule);
+    let unbounded = args.get(5).is_some_and(|arg| arg == "unbounded");
+    let board = Dimensions { width, height };
+    let default_grid = BoundedGrid {
+        topology: Topology::default(),
+        dimensions: board,
+    };
+    let rule_spec = match rulestr.as_str() {
+        "-" => None,
+        rulestr => Some(parse_rule_spec(rulestr)?),
+    };
+    // A topology argument or the rule's suffix sets the board's size before
+    // the patterns are read; otherwise the first pattern's header can.
+    let mut grid = match args.get(5) {
+        Some(arg) if !unbounded => Some(parse_topology(arg, board)?),
+        _ => rule_spec.map(|(_, rule_grid)| rule_grid.unwrap_or(default_grid)),
+    };
+    let mut layers = Vec::new();
+    if args[4].parse::<u32>().is_err() {
+        for spec in composition::parse_spec(&args[4])? {
+            let fit = grid.filter(|_| !unbounded).map(|grid| grid.dimensions);
+            let pattern = load_source(&spec.source, fit)?;
+            if grid.is_none() {
+                // The first pattern's header sizes the board when nothing
+                // else did, so the pattern is checked once the size is known.
+                let dimensions = grid.insert(pattern.bounded_grid.unwrap_or(default_grid)).dimensions;
+                let file = library::find(&spec.source).is_none() && !apgcode::is_apgcode(&spec.source);
+                if file && !unbounded && (pattern.width > dimensions.width || pattern.height > dimensions.height) {
+                    return Err(PatternError::TooLarge(pattern.width as i64, pattern.height as i64).into());
+                }
+            }
+            layers.push((pattern, spec));
+        }
+    }
+    let grid = grid.unwrap_or(default_grid);
+    let rule = match (rule_spec, layers.first()) {
+        (Some((rule, _)), _) => rule,
+        (None, Some((pattern, _))) => pattern.rule.unwrap_or_else(Rule::conway),
+        (None, None) => Rule::conway(),
+    };
+    let Dimensions { width, height } = grid.dimensions;
+
+    let board = if layers.is_empty() {
+        match Seeder::new(args[4].parse()?) {
+            Seeder::Random(_) => Seeder::Random(soup(&options)?).seed(width, height),
+            seeder => seeder.seed(width, height),
+        }
+    } else {
+        let placement = match options.get("at") {
+            Some(at) => at.parse()?,
+            None => Placement::Centered,
+        };
+        let composition = layers
+            .into_iter()
+            .fold(Composition::new(), |composition, (pattern, spec)| composition.with(spec.layer(pattern, placement)));
+        composition.seed(grid.dimensions, rule)?
+    };
+    let mut engine = if unbounded {
+        backend.build_unbounded(&board, rule)?
+    } else {
+        backend.build(&board, grid.dimensions, grid.topology, rule, threads)?
+    };
+    if let Some(generations) = options.get("advance") {
+        engine.step_n(generations.parse()?);
+        println!("generation {}: population {}", engine.generation(), engine.population());
+    }
+    let save_settings = SaveSettings {
+        path: options.get("save").cloned().unwrap_or_else(|| "board.rle".to_string()),
+        source: args[1..].join(" "),
+        bounded_grid: if unbounded { None } else { Some(grid) },
+    };
+    let game_state = GameState::new(width, height, engine, unbounded, save_settings);
 
     let context = BTermBuilder::simple(width, height)
         .unwrap()
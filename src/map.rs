@@ -0,0 +1,76 @@
+use crate::resources::{RuleParseError, Transitions};
+
+pub const PREFIX: &str = "MAP";
+
+const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Base64 digits needed for the 512 bits of a table.
+pub const LENGTH: usize = 86;
+
+/// Decodes the base64 body of a MAP rule, without the `MAP` prefix: bit
+/// `i` of the table, most significant first, is the next state after the
+/// neighbourhood with index `i`.
+pub fn decode(body: &str) -> Result<Transitions, RuleParseError> {
+    let invalid = || RuleParseError::InvalidMap(body.to_string());
+    if body.len() != LENGTH {
+        return Err(invalid());
+    }
+    let mut table = Transitions::default();
+    for (position, c) in body.bytes().enumerate() {
+        let digit = ALPHABET.iter().position(|&d| d == c).ok_or_else(invalid)?;
+        for bit in 0..6 {
+            let index = position * 6 + bit;
+            if index < 512 && digit & (1 << (5 - bit)) != 0 {
+                table.insert(index as u16);
+            }
+        }
+    }
+    Ok(table)
+}
+
+/// The base64 body of the MAP rule for `table`.
+pub fn encode(table: &Transitions) -> String {
+    (0..LENGTH)
+        .map(|position| {
+            let digit = (0..6)
+                .map(|bit| position * 6 + bit)
+                .filter(|&index| index < 512 && table.contains(index as u16))
+                .fold(0, |digit, index| digit | 1 << (5 - index % 6));
+            ALPHABET[digit] as char
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::neighbour_bit;
+
+    #[test]
+    fn test_round_trip() {
+        let mut table = Transitions::default();
+        for index in [0, 1, 5, 6, 7, 255, 256, 510, 511] {
+            table.insert(index);
+        }
+        let body = encode(&table);
+        assert_eq!(body.len(), LENGTH);
+        assert_eq!(decode(&body), Ok(table));
+    }
+
+    #[test]
+    fn test_first_index_is_the_top_bit() {
+        let mut table = Transitions::default();
+        table.insert(0);
+        table.insert(neighbour_bit(-1, -1));
+        let body = encode(&table);
+        assert!(body.starts_with('g'));
+        assert_eq!(&body[42..43], "C");
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(decode("ABC"), Err(RuleParseError::InvalidMap("ABC".to_string())));
+        let bad = format!("{}!", "A".repeat(LENGTH - 1));
+        assert_eq!(decode(&bad), Err(RuleParseError::InvalidMap(bad.clone())));
+    }
+}
@@ -0,0 +1,207 @@
+use std::fmt;
+
+use crate::neighbourhood::Neighbourhood;
+
+/// A multi-state rule whose next state is any function of a cell's state
+/// and its neighbours' states, for automata such as Wireworld that birth
+/// and survival counts cannot describe.
+#[derive(Clone, Copy)]
+pub struct NamedRule {
+    /// The name rules are written with, as in Golly.
+    pub name: &'static str,
+    pub description: &'static str,
+    pub states: u8,
+    /// `Moore(1)` or `VonNeumann(1)`.
+    pub neighbourhood: Neighbourhood,
+    /// The next state of a cell from its own state and those of its
+    /// neighbours, in the order of `Neighbourhood::offsets`. A dead cell
+    /// among dead neighbours must stay dead.
+    pub next_state: fn(u8, &[u8]) -> u8,
+    /// The colour of each state, dead first.
+    pub colours: &'static [(u8, u8, u8)],
+}
+
+impl fmt::Debug for NamedRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NamedRule({})", self.name)
+    }
+}
+
+/// Rules are told apart by name.
+impl PartialEq for NamedRule {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for NamedRule {}
+
+pub const RULES: &[NamedRule] = &[
+    NamedRule {
+        name: "WireWorld",
+        description: "Electrons (head, then tail) running along conductors; for logic circuits.",
+        states: 4,
+        neighbourhood: Neighbourhood::Moore(1),
+        next_state: wireworld,
+        colours: &[(0, 0, 0), (0, 128, 255), (255, 255, 255), (255, 128, 0)],
+    },
+    NamedRule {
+        name: "BriansBrain",
+        description: "Firing cells fire their neighbours once, then rest for a generation.",
+        states: 3,
+        neighbourhood: Neighbourhood::Moore(1),
+        next_state: brians_brain,
+        colours: &[(0, 0, 0), (255, 255, 255), (0, 64, 255)],
+    },
+    NamedRule {
+        name: "Langtons-Loops",
+        description: "Christopher Langton's self-reproducing loops, 8 states.",
+        states: 8,
+        neighbourhood: Neighbourhood::VonNeumann(1),
+        next_state: langtons_loops,
+        colours: &[
+            (0, 0, 0),
+            (0, 0, 255),
+            (255, 0, 0),
+            (0, 255, 0),
+            (255, 255, 0),
+            (255, 0, 255),
+            (255, 255, 255),
+            (0, 255, 255),
+        ],
+    },
+];
+
+/// The rule called `name`, ignoring case, spaces, `-`, `_` and `'`.
+pub fn find(name: &str) -> Option<&'static NamedRule> {
+    let key = |name: &str| -> String {
+        name.chars().filter(|c| !matches!(c, ' ' | '-' | '_' | '\'')).flat_map(char::to_lowercase).collect()
+    };
+    let wanted = key(name);
+    RULES.iter().find(|rule| key(rule.name) == wanted)
+}
+
+/// Empty (0), electron head (1), electron tail (2) and conductor (3): a
+/// head becomes a tail, a tail a conductor, and a conductor a head when one
+/// or two of its neighbours are heads.
+fn wireworld(state: u8, neighbours: &[u8]) -> u8 {
+    match state {
+        1 => 2,
+        2 => 3,
+        3 if matches!(neighbours.iter().filter(|&&n| n == 1).count(), 1 | 2) => 1,
+        3 => 3,
+        _ => 0,
+    }
+}
+
+/// Ready (0), firing (1) and refractory (2): a ready cell fires when
+/// exactly two neighbours fire, and a firing cell rests for a generation.
+fn brians_brain(state: u8, neighbours: &[u8]) -> u8 {
+    match state {
+        0 if neighbours.iter().filter(|&&n| n == 1).count() == 2 => 1,
+        1 => 2,
+        _ => 0,
+    }
+}
+
+/// Langton's transitions as Golly writes them: the cell, its north, east,
+/// south and west neighbours and then the next state, which holds for
+/// every rotation of the neighbours. Sorted; cells no entry matches keep
+/// their state.
+const LANGTON: &[&str] = &[
+    "000000", "000012", "000020", "000030", "000050", "000063", "000071", "000112", "000122", "000132", "000212",
+    "000220", "000230", "000262", "000272", "000320", "000525", "000622", "000722", "001022", "001120", "002020",
+    "002030", "002050", "002125", "002220", "002322", "005222", "012321", "012421", "012525", "012621", "012721",
+    "012751", "014221", "014321", "014421", "014721", "016251", "017221", "017255", "017521", "017621", "017721",
+    "025271", "100011", "100061", "100077", "100111", "100121", "100211", "100244", "100277", "100511", "101011",
+    "101111", "101244", "101277", "102026", "102121", "102211", "102244", "102263", "102277", "102327", "102424",
+    "102626", "102644", "102677", "102710", "102727", "105427", "111121", "111221", "111244", "111251", "111261",
+    "111277", "111522", "112121", "112221", "112244", "112251", "112277", "112321", "112424", "112621", "112727",
+    "113221", "122244", "122277", "122434", "122547", "123244", "123277", "124255", "124267", "125275", "200012",
+    "200022", "200042", "200071", "200122", "200152", "200212", "200222", "200232", "200242", "200250", "200262",
+    "200272", "200326", "200423", "200517", "200522", "200575", "200722", "201022", "201122", "201222", "201422",
+    "201722", "202022", "202032", "202052", "202073", "202122", "202152", "202212", "202222", "202272", "202321",
+    "202422", "202452", "202520", "202552", "202622", "202722", "203122", "203216", "203226", "203422", "204222",
+    "205122", "205212", "205222", "205521", "205725", "206222", "206722", "207122", "207222", "207422", "207722",
+    "211222", "211261", "212222", "212242", "212262", "212272", "214222", "215222", "216222", "217222", "222272",
+    "222442", "222462", "222762", "222772", "300013", "300022", "300041", "300076", "300123", "300421", "300622",
+    "301021", "301220", "302511", "401120", "401220", "401250", "402120", "402221", "402326", "402520", "403221",
+    "500022", "500215", "500225", "500232", "500272", "500520", "502022", "502122", "502152", "502220", "502244",
+    "502722", "512122", "512220", "512422", "512722", "600011", "600021", "602120", "612125", "612131", "612225",
+    "700077", "701120", "701220", "701250", "702120", "702221", "702251", "702321", "702525", "702720",
+];
+
+fn langtons_loops(state: u8, neighbours: &[u8]) -> u8 {
+    rotate4(LANGTON, state, neighbours)
+}
+
+/// Looks a von Neumann cell up in a sorted table of Golly's `rotate4`
+/// entries like `LANGTON`, keeping its state when no entry matches.
+fn rotate4(table: &[&str], state: u8, neighbours: &[u8]) -> u8 {
+    // Von Neumann neighbours come north, west, east, south.
+    let around = [neighbours[0], neighbours[2], neighbours[3], neighbours[1]];
+    for turn in 0..4 {
+        let mut key = [b'0' + state; 5];
+        for i in 0..4 {
+            key[i + 1] = b'0' + around[(i + turn) % 4];
+        }
+        if let Ok(index) = table.binary_search_by(|entry| entry.as_bytes()[..5].cmp(&key[..])) {
+            return table[index].as_bytes()[5] - b'0';
+        }
+    }
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_ignores_case_and_separators() {
+        assert_eq!(find("wireworld").map(|rule| rule.name), Some("WireWorld"));
+        assert_eq!(find("Brian's Brain").map(|rule| rule.name), Some("BriansBrain"));
+        assert_eq!(find("langtons_loops").map(|rule| rule.name), Some("Langtons-Loops"));
+        assert_eq!(find("B3/S23"), None);
+    }
+
+    #[test]
+    fn test_every_rule_has_a_colour_per_state_and_keeps_dead_cells_dead() {
+        for rule in RULES {
+            assert_eq!(rule.colours.len(), rule.states as usize, "{}", rule.name);
+            let dead = vec![0; rule.neighbourhood.size()];
+            assert_eq!((rule.next_state)(0, &dead), 0, "{}", rule.name);
+        }
+    }
+
+    #[test]
+    fn test_wireworld() {
+        let mut neighbours = [0, 3, 3, 0, 0, 0, 0, 0];
+        assert_eq!(wireworld(1, &neighbours), 2);
+        assert_eq!(wireworld(2, &neighbours), 3);
+        assert_eq!(wireworld(3, &neighbours), 3);
+        neighbours[0] = 1;
+        assert_eq!(wireworld(3, &neighbours), 1);
+        neighbours[1..4].copy_from_slice(&[1, 1, 1]);
+        assert_eq!(wireworld(3, &neighbours), 3);
+        assert_eq!(wireworld(0, &neighbours), 0);
+    }
+
+    #[test]
+    fn test_brians_brain() {
+        assert_eq!(brians_brain(0, &[1, 1, 0, 0, 2, 2, 2, 0]), 1);
+        assert_eq!(brians_brain(0, &[1, 1, 1, 0, 0, 0, 0, 0]), 0);
+        assert_eq!(brians_brain(1, &[0; 8]), 2);
+        assert_eq!(brians_brain(2, &[1, 1, 0, 0, 0, 0, 0, 0]), 0);
+    }
+
+    #[test]
+    fn test_langton_table_is_sorted_and_rotations_match() {
+        assert!(LANGTON.windows(2).all(|pair| pair[0] < pair[1]));
+        // "102026": a 1 with 0 north, 2 east, 0 south and 2 west becomes 6,
+        // in every rotation. Neighbours are passed north, west, east, south.
+        assert_eq!(langtons_loops(1, &[0, 2, 2, 0]), 6);
+        assert_eq!(langtons_loops(1, &[2, 0, 0, 2]), 6);
+        // No entry matches, so the cell keeps its state.
+        assert_eq!(langtons_loops(6, &[6, 6, 6, 6]), 6);
+    }
+}
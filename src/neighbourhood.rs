@@ -0,0 +1,213 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::resources::{neighbour_bit, RuleParseError};
+
+/// Largest radius of a Moore, von Neumann or custom neighbourhood.
+pub const MAX_RADIUS: u8 = 3;
+
+/// The cells whose live count decides a cell's next state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Neighbourhood {
+    /// The square of cells within `radius` in both directions.
+    Moore(u8),
+    /// The diamond of cells within `radius` steps along the axes.
+    VonNeumann(u8),
+    /// Golly's hexagonal grid on square cells: the Moore neighbourhood
+    /// without the north-east and south-west cells.
+    Hexagonal,
+    /// Any cells within `radius`. Bit `(dy + radius) * (2 * radius + 1) +
+    /// dx + radius` of `cells` is set for the cell at offset `(dx, dy)`; the
+    /// centre is never counted.
+    Custom { radius: u8, cells: u64 },
+}
+
+impl Default for Neighbourhood {
+    fn default() -> Self {
+        Neighbourhood::Moore(1)
+    }
+}
+
+impl Neighbourhood {
+    pub fn radius(&self) -> i32 {
+        match *self {
+            Neighbourhood::Moore(radius) | Neighbourhood::VonNeumann(radius) | Neighbourhood::Custom { radius, .. } => {
+                radius as i32
+            }
+            Neighbourhood::Hexagonal => 1,
+        }
+    }
+
+    pub fn contains(&self, dx: i32, dy: i32) -> bool {
+        let radius = self.radius();
+        if (dx, dy) == (0, 0) || dx.abs() > radius || dy.abs() > radius {
+            return false;
+        }
+        match *self {
+            Neighbourhood::Moore(_) => true,
+            Neighbourhood::VonNeumann(_) => dx.abs() + dy.abs() <= radius,
+            Neighbourhood::Hexagonal => dx != -dy,
+            Neighbourhood::Custom { cells, .. } => {
+                let side = 2 * radius + 1;
+                cells & (1 << ((dy + radius) * side + dx + radius)) != 0
+            }
+        }
+    }
+
+    /// The offsets of the cells in the neighbourhood, row by row.
+    pub fn offsets(&self) -> Vec<(i32, i32)> {
+        let radius = self.radius();
+        (-radius..=radius)
+            .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| self.contains(dx, dy))
+            .collect()
+    }
+
+    /// How many cells the neighbourhood has, the largest live count.
+    pub fn size(&self) -> usize {
+        self.offsets().len()
+    }
+
+    /// The neighbourhood as `neighbour_bit` bits of a 3x3 index, for
+    /// neighbourhoods of radius 1.
+    pub fn mask(&self) -> u16 {
+        self.offsets().into_iter().fold(0, |mask, (dx, dy)| mask | neighbour_bit(dx, dy))
+    }
+}
+
+impl fmt::Display for Neighbourhood {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Neighbourhood::Moore(1) => Ok(()),
+            Neighbourhood::Moore(radius) => write!(f, "M{}", radius),
+            Neighbourhood::VonNeumann(1) => write!(f, "V"),
+            Neighbourhood::VonNeumann(radius) => write!(f, "V{}", radius),
+            Neighbourhood::Hexagonal => write!(f, "H"),
+            Neighbourhood::Custom { radius, cells } => {
+                let side = 2 * radius as usize + 1;
+                let bits = side * side;
+                write!(f, "@")?;
+                for digit in 0..bits.div_ceil(4) {
+                    let value = (0..4)
+                        .map(|bit| digit * 4 + bit)
+                        .filter(|&index| index < bits && cells & (1 << index) != 0)
+                        .fold(0, |value, index| value | 8 >> (index % 4));
+                    write!(f, "{:x}", value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Parses the suffix that follows a rule's B and S sections: `M`, `V` or
+/// `H` with an optional radius, as in `V` or `M2`, or `@` and the hex digits
+/// of a custom neighbourhood. The digits give the square around the cell
+/// row by row, first cell in the top bit; 3, 7 or 13 digits make a radius
+/// of 1, 2 or 3.
+impl FromStr for Neighbourhood {
+    type Err = RuleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || RuleParseError::InvalidNeighbourhoodShape(s.to_string());
+        if let Some(digits) = s.strip_prefix('@') {
+            let radius = match digits.len() {
+                3 => 1,
+                7 => 2,
+                13 => 3,
+                _ => return Err(invalid()),
+            };
+            let side = 2 * radius + 1;
+            let mut cells = 0u64;
+            for (position, c) in digits.chars().enumerate() {
+                let value = c.to_digit(16).ok_or_else(invalid)?;
+                for bit in (0..4).filter(|bit| value & (8 >> bit) != 0) {
+                    let index = position * 4 + bit;
+                    if index < side * side && index != side * side / 2 {
+                        cells |= 1 << index;
+                    }
+                }
+            }
+            return Ok(Neighbourhood::Custom { radius: radius as u8, cells });
+        }
+
+        let mut chars = s.chars();
+        let shape = chars.next().map(|c| c.to_ascii_uppercase());
+        let radius = match chars.as_str() {
+            "" => 1,
+            digits => digits.parse().map_err(|_| invalid())?,
+        };
+        if !(1..=MAX_RADIUS).contains(&radius) {
+            return Err(invalid());
+        }
+        match shape {
+            Some('M') => Ok(Neighbourhood::Moore(radius)),
+            Some('V') => Ok(Neighbourhood::VonNeumann(radius)),
+            Some('H') if radius == 1 => Ok(Neighbourhood::Hexagonal),
+            None => Ok(Neighbourhood::Moore(1)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(Neighbourhood::Moore(1).size(), 8);
+        assert_eq!(Neighbourhood::Moore(2).size(), 24);
+        assert_eq!(Neighbourhood::Moore(3).size(), 48);
+        assert_eq!(Neighbourhood::VonNeumann(1).size(), 4);
+        assert_eq!(Neighbourhood::VonNeumann(2).size(), 12);
+        assert_eq!(Neighbourhood::VonNeumann(3).size(), 24);
+        assert_eq!(Neighbourhood::Hexagonal.size(), 6);
+    }
+
+    #[test]
+    fn test_hexagonal_leaves_out_one_diagonal() {
+        let hexagonal = Neighbourhood::Hexagonal;
+        assert!(hexagonal.contains(-1, -1) && hexagonal.contains(1, 1));
+        assert!(!hexagonal.contains(1, -1) && !hexagonal.contains(-1, 1));
+        let orthogonal = neighbour_bit(0, -1) | neighbour_bit(-1, 0) | neighbour_bit(1, 0) | neighbour_bit(0, 1);
+        assert_eq!(Neighbourhood::VonNeumann(1).mask(), orthogonal);
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        for (text, neighbourhood) in [
+            ("M", Neighbourhood::Moore(1)),
+            ("M2", Neighbourhood::Moore(2)),
+            ("v", Neighbourhood::VonNeumann(1)),
+            ("V3", Neighbourhood::VonNeumann(3)),
+            ("H", Neighbourhood::Hexagonal),
+        ] {
+            assert_eq!(text.parse(), Ok(neighbourhood), "{}", text);
+            assert_eq!(neighbourhood.to_string().parse(), Ok(neighbourhood));
+        }
+        assert_eq!(Neighbourhood::VonNeumann(1).to_string(), "V");
+        assert_eq!(Neighbourhood::Moore(1).to_string(), "");
+    }
+
+    #[test]
+    fn test_custom_neighbourhoods() {
+        // The four orthogonal neighbours: .o. / o.o / .o.
+        let plus: Neighbourhood = "@550".parse().unwrap();
+        assert_eq!(plus.offsets(), Neighbourhood::VonNeumann(1).offsets());
+        assert_eq!(plus.to_string(), "@550");
+        // The centre bit is ignored.
+        assert_eq!("@5d0".parse(), Ok(plus));
+
+        let knights: Neighbourhood = "@5441150".parse().unwrap();
+        assert_eq!(knights.offsets(), vec![(-1, -2), (1, -2), (-2, -1), (2, -1), (-2, 1), (2, 1), (-1, 2), (1, 2)]);
+        assert_eq!(knights.to_string().parse(), Ok(knights));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for text in ["M4", "V0", "H2", "X", "@12", "@12g", "Mx"] {
+            assert_eq!(text.parse::<Neighbourhood>(), Err(RuleParseError::InvalidNeighbourhoodShape(text.to_string())));
+        }
+    }
+}
@@ -0,0 +1,433 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::life;
+use crate::macrocell;
+use crate::plaintext;
+use crate::resources::{Dimensions, Rule, RuleParseError};
+use crate::rle;
+use crate::seeder::Grid;
+use crate::topology::BoundedGrid;
+use crate::universe::Simulation;
+
+/// A pattern read from or written to a file. `cells` holds the cells that are not dead,
+/// relative to the top-left corner of a `width` x `height` box.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Pattern {
+    pub name: Option<String>,
+    pub author: Option<String>,
+    pub comments: Vec<String>,
+    pub rule: Option<Rule>,
+    pub bounded_grid: Option<BoundedGrid>,
+    pub width: i32,
+    pub height: i32,
+    pub cells: Grid,
+}
+
+impl Pattern {
+    /// A pattern without metadata whose box is the bounding box of the
+    /// non-dead cells of `grid`.
+    pub fn from_grid(grid: &Grid) -> Self {
+        Pattern::from_cells(grid.iter().map(|(&(x, y), &state)| ((x as i64, y as i64), state as u8)))
+    }
+
+    /// The non-dead cells of `simulation` inside `region`, or all of them,
+    /// with the simulation's rule.
+    pub fn capture(simulation: &dyn Simulation, region: Option<Region>) -> Self {
+        let cells = simulation.cells().into_iter();
+        let mut pattern = match region {
+            Some(region) => Pattern::from_cells(cells.filter(|&((x, y), _)| region.contains(x, y))),
+            None => Pattern::from_cells(cells),
+        };
+        pattern.rule = Some(*simulation.rule());
+        pattern
+    }
+
+    /// A pattern without metadata boxing the non-dead `cells`.
+    pub fn from_cells(cells: impl Iterator<Item = ((i64, i64), u8)>) -> Self {
+        let live: Vec<((i64, i64), u8)> = cells.filter(|&(_, state)| state != 0).collect();
+        let min_x = live.iter().map(|&((x, _), _)| x).min().unwrap_or(0);
+        let min_y = live.iter().map(|&((_, y), _)| y).min().unwrap_or(0);
+        let max_x = live.iter().map(|&((x, _), _)| x).max().unwrap_or(-1);
+        let max_y = live.iter().map(|&((_, y), _)| y).max().unwrap_or(-1);
+        Pattern {
+            width: (max_x - min_x + 1) as i32,
+            height: (max_y - min_y + 1) as i32,
+            cells: live
+                .iter()
+                .map(|&((x, y), state)| (((x - min_x) as i32, (y - min_y) as i32), state as i32))
+                .collect(),
+            ..Pattern::default()
+        }
+    }
+
+    /// The pattern with its box grown to hold cells that lie outside
+    /// `width` by `height`, and the cells moved so that none is negative.
+    pub fn boxed(&self) -> Pattern {
+        let xs = || self.cells.keys().map(|&(x, _)| x);
+        let ys = || self.cells.keys().map(|&(_, y)| y);
+        let (left, top) = (xs().min().unwrap_or(0).min(0), ys().min().unwrap_or(0).min(0));
+        let right = xs().map(|x| x + 1).max().unwrap_or(0).max(self.width);
+        let bottom = ys().map(|y| y + 1).max().unwrap_or(0).max(self.height);
+        Pattern {
+            width: right - left,
+            height: bottom - top,
+            cells: self.cells.iter().map(|(&(x, y), &state)| ((x - left, y - top), state)).collect(),
+            ..self.clone()
+        }
+    }
+
+    /// Fails for patterns with cells above state 1, which two-state formats
+    /// cannot hold.
+    pub fn check_two_state(&self) -> Result<(), PatternError> {
+        match self.cells.values().find(|&&state| state > 1) {
+            Some(&state) => Err(PatternError::MultiState(state)),
+            None => Ok(()),
+        }
+    }
+
+    /// The pattern's cells moved onto a board of `dimensions`. Cells that
+    /// land off the board are kept for unbounded universes to use.
+    pub fn place(&self, dimensions: Dimensions, placement: Placement) -> Grid {
+        let (left, top) = match placement {
+            Placement::Centered => ((dimensions.width - self.width) / 2, (dimensions.height - self.height) / 2),
+            Placement::At(x, y) => (x, y),
+        };
+        self.cells.iter().map(|(&(x, y), &state)| ((left + x, top + y), state)).collect()
+    }
+
+    /// The pattern mirrored left to right.
+    pub fn reflected(&self) -> Pattern {
+        let cells = self.cells.iter().map(|(&(x, y), &state)| ((self.width - 1 - x, y), state)).collect();
+        Pattern { cells, ..self.clone() }
+    }
+
+    /// The pattern turned clockwise by `rotation`.
+    pub fn rotated(&self, rotation: Rotation) -> Pattern {
+        let (width, height) = (self.width, self.height);
+        let turn = |x: i32, y: i32| match rotation {
+            Rotation::None => (x, y),
+            Rotation::Quarter => (height - 1 - y, x),
+            Rotation::Half => (width - 1 - x, height - 1 - y),
+            Rotation::ThreeQuarters => (y, width - 1 - x),
+        };
+        let (width, height) = match rotation {
+            Rotation::Quarter | Rotation::ThreeQuarters => (height, width),
+            _ => (width, height),
+        };
+        let cells = self.cells.iter().map(|(&(x, y), &state)| (turn(x, y), state)).collect();
+        Pattern { width, height, cells, ..self.clone() }
+    }
+}
+
+/// A rectangle of cells with its top-left corner at `x`, `y`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: i64,
+    pub y: i64,
+    pub width: i64,
+    pub height: i64,
+}
+
+impl Region {
+    pub fn contains(&self, x: i64, y: i64) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+/// Where a pattern goes on the board.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Placement {
+    #[default]
+    Centered,
+    /// The top-left corner of the pattern's box goes at this position.
+    At(i32, i32),
+}
+
+/// A clockwise turn by a multiple of 90 degrees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+impl FromStr for Rotation {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "0" => Ok(Rotation::None),
+            "90" => Ok(Rotation::Quarter),
+            "180" => Ok(Rotation::Half),
+            "270" => Ok(Rotation::ThreeQuarters),
+            _ => Err(PatternError::InvalidRotation(s.to_string())),
+        }
+    }
+}
+
+impl FromStr for Placement {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("center") {
+            return Ok(Placement::Centered);
+        }
+        let coordinate = |part: &str| part.trim().parse().map_err(|_| PatternError::InvalidPlacement(s.to_string()));
+        match s.split_once(',') {
+            Some((x, y)) => Ok(Placement::At(coordinate(x)?, coordinate(y)?)),
+            None => Err(PatternError::InvalidPlacement(s.to_string())),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PatternError {
+    Io(String),
+    MissingHeader,
+    InvalidHeader(String),
+    InvalidRule(RuleParseError),
+    UnexpectedCharacter(char),
+    InvalidPlacement(String),
+    MultiState(i32),
+    InvalidNode(String),
+    TooLarge(i64, i64),
+    InvalidRotation(String),
+    InvalidLayer(String),
+    UnsupportedRule(String),
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PatternError::Io(message) => write!(f, "could not read pattern: {}", message),
+            PatternError::MissingHeader => write!(f, "pattern has no 'x = .., y = ..' header"),
+            PatternError::InvalidHeader(header) => write!(f, "invalid pattern header '{}'", header),
+            PatternError::InvalidRule(e) => write!(f, "invalid pattern rule: {}", e),
+            PatternError::UnexpectedCharacter(c) => write!(f, "unexpected character '{}' in pattern", c),
+            PatternError::InvalidPlacement(s) => write!(f, "invalid placement '{}', expected 'x,y' or 'center'", s),
+            PatternError::MultiState(state) => write!(f, "format cannot hold cell state {}", state),
+            PatternError::InvalidNode(line) => write!(f, "invalid macrocell node '{}'", line),
+            PatternError::TooLarge(width, height) => write!(f, "pattern is {}x{}, larger than the board", width, height),
+            PatternError::InvalidRotation(s) => write!(f, "invalid rotation '{}', expected 0, 90, 180 or 270", s),
+            PatternError::InvalidLayer(s) => write!(f, "invalid pattern layer '{}'", s),
+            PatternError::UnsupportedRule(rule) => write!(f, "format cannot hold rule {}", rule),
+        }
+    }
+}
+
+impl Error for PatternError {}
+
+/// The pattern file formats that can be read and written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Rle,
+    Plaintext,
+    Life105,
+    Life106,
+    Macrocell,
+}
+
+impl Format {
+    /// Tells the format of a pattern file from its contents.
+    pub fn detect(text: &str) -> Format {
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        let first = lines.clone().next().unwrap_or_default();
+        if first.starts_with(macrocell::HEADER) {
+            Format::Macrocell
+        } else if first.starts_with(life::HEADER_106) {
+            Format::Life106
+        } else if first.starts_with(life::HEADER_105) {
+            Format::Life105
+        } else if first.starts_with('#') || lines.any(|line| line.starts_with('x')) {
+            Format::Rle
+        } else {
+            Format::Plaintext
+        }
+    }
+
+    /// The format to save to `path` in, by its extension: `.cells` is
+    /// plaintext, `.lif` and `.life` are Life 1.06, `.mc` macrocell, anything
+    /// else RLE.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Format {
+        let extension = path.as_ref().extension().and_then(|extension| extension.to_str());
+        match extension.map(|extension| extension.to_ascii_lowercase()).as_deref() {
+            Some("cells") => Format::Plaintext,
+            Some("lif" | "life") => Format::Life106,
+            Some("mc") => Format::Macrocell,
+            _ => Format::Rle,
+        }
+    }
+
+    pub fn parse(&self, text: &str) -> Result<Pattern, PatternError> {
+        match self {
+            Format::Rle => rle::parse(text),
+            Format::Plaintext => plaintext::parse(text),
+            Format::Life105 => life::parse_105(text),
+            Format::Life106 => life::parse_106(text),
+            Format::Macrocell => macrocell::parse(text)?.to_pattern(None),
+        }
+    }
+
+    pub fn write(&self, pattern: &Pattern) -> Result<String, PatternError> {
+        match self {
+            Format::Rle => Ok(rle::write(pattern)),
+            Format::Plaintext => plaintext::write(pattern),
+            Format::Life105 => life::write_105(pattern),
+            Format::Life106 => life::write_106(pattern),
+            Format::Macrocell => Ok(macrocell::write(pattern)),
+        }
+    }
+}
+
+/// Parses a pattern in whichever format its contents are in.
+pub fn parse(text: &str) -> Result<Pattern, PatternError> {
+    Format::detect(text).parse(text)
+}
+
+/// Reads a pattern file in any supported format, failing when it is bigger
+/// than `fit`. Macrocell files are only flattened once they are known to fit.
+pub fn load<P: AsRef<Path>>(path: P, fit: Option<Dimensions>) -> Result<Pattern, PatternError> {
+    let text = fs::read_to_string(path).map_err(|e| PatternError::Io(e.to_string()))?;
+    let pattern = match Format::detect(&text) {
+        Format::Macrocell => return macrocell::parse(&text)?.to_pattern(fit),
+        format => format.parse(&text)?,
+    };
+    match fit {
+        Some(fit) if pattern.width > fit.width || pattern.height > fit.height => {
+            Err(PatternError::TooLarge(pattern.width as i64, pattern.height as i64))
+        }
+        _ => Ok(pattern),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glider() -> Pattern {
+        let mut grid = Grid::new();
+        for pos in [(11, 10), (12, 11), (10, 12), (11, 12), (12, 12), (0, 0)] {
+            grid.insert(pos, (pos != (0, 0)) as i32);
+        }
+        Pattern::from_grid(&grid)
+    }
+
+    #[test]
+    fn test_from_grid_normalizes_to_bounding_box() {
+        let pattern = glider();
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.cells.len(), 5);
+        assert_eq!(pattern.cells.get(&(1, 0)), Some(&1));
+        assert_eq!(pattern.cells.get(&(0, 0)), None);
+    }
+
+    #[test]
+    fn test_place_centered_and_at_offset() {
+        let pattern = glider();
+        let dimensions = Dimensions { width: 11, height: 7 };
+        let centered = pattern.place(dimensions, Placement::Centered);
+        assert_eq!(centered.get(&(5, 2)), Some(&1));
+        assert_eq!(centered.get(&(6, 4)), Some(&1));
+
+        let moved = pattern.place(dimensions, Placement::At(-1, 20));
+        assert_eq!(moved.get(&(0, 20)), Some(&1));
+        assert_eq!(moved.len(), 5);
+    }
+
+    #[test]
+    fn test_reflect_and_rotate() {
+        let pattern = rle::parse_body("2o$o$o!").unwrap();
+        let cells = |pattern: Pattern| pattern.cells.keys().copied().collect::<Vec<_>>();
+        assert_eq!(cells(pattern.reflected()), vec![(0, 0), (1, 0), (1, 1), (1, 2)]);
+
+        let quarter = pattern.rotated(Rotation::Quarter);
+        assert_eq!((quarter.width, quarter.height), (3, 2));
+        assert_eq!(cells(quarter.clone()), vec![(0, 0), (1, 0), (2, 0), (2, 1)]);
+        assert_eq!(cells(pattern.rotated(Rotation::Half)), vec![(0, 2), (1, 0), (1, 1), (1, 2)]);
+        assert_eq!(quarter.rotated(Rotation::ThreeQuarters), pattern);
+        assert_eq!(quarter.rotated(Rotation::Quarter), pattern.rotated(Rotation::Half));
+        assert_eq!(pattern.rotated(Rotation::None), pattern);
+    }
+
+    #[test]
+    fn test_capture_simulation() {
+        let mut universe = crate::sparse::SparseUniverse::new(Rule::conway());
+        for (x, y) in [(-10, 4), (-9, 4), (5, 6)] {
+            universe.set(x, y, 1);
+        }
+
+        let whole = Pattern::capture(&universe, None);
+        assert_eq!((whole.width, whole.height), (16, 3));
+        assert_eq!(whole.rule, Some(Rule::conway()));
+        assert_eq!(whole.cells.get(&(15, 2)), Some(&1));
+
+        let region = Region { x: -20, y: 0, width: 20, height: 10 };
+        let part = Pattern::capture(&universe, Some(region));
+        assert_eq!((part.width, part.height), (2, 1));
+        assert_eq!(part.cells.len(), 2);
+    }
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(Format::detect("#N Glider\nx = 3, y = 3\nbo$2bo$3o!"), Format::Rle);
+        assert_eq!(Format::detect("x = 3, y = 1\n3o!"), Format::Rle);
+        assert_eq!(Format::detect("!Name: Glider\n.O.\n..O\nOOO"), Format::Plaintext);
+        assert_eq!(Format::detect(".O.\n..O\nOOO"), Format::Plaintext);
+        assert_eq!(Format::detect("\n#Life 1.05\n#P 0 0\n*"), Format::Life105);
+        assert_eq!(Format::detect("#Life 1.06\n0 0"), Format::Life106);
+        assert_eq!(Format::detect("[M2] (golly 4.2)\n*$\n4 1 0 0 0"), Format::Macrocell);
+    }
+
+    #[test]
+    fn test_every_format_reads_the_same_glider() {
+        let sources = [
+            "x = 3, y = 3\nbo$2bo$3o!",
+            ".O.\n..O\nOOO",
+            "#Life 1.05\n#P -1 -1\n.*\n..*\n***",
+            "#Life 1.06\n0 -1\n1 0\n-1 1\n0 1\n1 1",
+            "[M2]\n.*$..*$***$\n4 0 0 0 1",
+        ];
+        let expected = parse(sources[0]).unwrap();
+        for source in sources {
+            let pattern = parse(source).unwrap();
+            assert_eq!(pattern.cells, expected.cells, "{}", source);
+            for format in [Format::Rle, Format::Plaintext, Format::Life105, Format::Life106, Format::Macrocell] {
+                let written = format.write(&pattern).unwrap();
+                assert_eq!(Format::detect(&written), format);
+                assert_eq!(parse(&written).unwrap().cells, expected.cells, "{:?}", format);
+            }
+        }
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(Format::from_path("glider.cells"), Format::Plaintext);
+        assert_eq!(Format::from_path("glider.LIF"), Format::Life106);
+        assert_eq!(Format::from_path("glider.rle"), Format::Rle);
+        assert_eq!(Format::from_path("metapixel.mc"), Format::Macrocell);
+        assert_eq!(Format::from_path("glider"), Format::Rle);
+    }
+
+    #[test]
+    fn test_parse_rotation() {
+        assert_eq!("270".parse(), Ok(Rotation::ThreeQuarters));
+        assert_eq!("45".parse::<Rotation>(), Err(PatternError::InvalidRotation("45".to_string())));
+    }
+
+    #[test]
+    fn test_parse_placement() {
+        assert_eq!("center".parse(), Ok(Placement::Centered));
+        assert_eq!("3, -4".parse(), Ok(Placement::At(3, -4)));
+        assert_eq!(
+            "3".parse::<Placement>(),
+            Err(PatternError::InvalidPlacement("3".to_string()))
+        );
+    }
+}
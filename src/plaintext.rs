@@ -0,0 +1,105 @@
+use std::fmt::Write;
+
+use crate::pattern::{Pattern, PatternError};
+
+/// Parses a plaintext `.cells` pattern: `!` comment lines, which may give
+/// `!Name: ..` and `!Author: ..`, then one row per line with `.` for dead
+/// and `O` (or `*`) for live cells.
+pub fn parse(text: &str) -> Result<Pattern, PatternError> {
+    let mut pattern = Pattern::default();
+    let mut y = 0;
+    for line in text.lines().map(str::trim_end) {
+        if let Some(comment) = line.strip_prefix('!') {
+            if let Some(name) = comment.strip_prefix("Name:") {
+                pattern.name = Some(name.trim().to_string());
+            } else if let Some(author) = comment.strip_prefix("Author:") {
+                pattern.author = Some(author.trim().to_string());
+            } else {
+                pattern.comments.push(comment.trim().to_string());
+            }
+            continue;
+        }
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                '.' => {}
+                'O' | '*' => {
+                    pattern.cells.insert((x as i32, y), 1);
+                }
+                _ => return Err(PatternError::UnexpectedCharacter(c)),
+            }
+        }
+        pattern.width = pattern.width.max(line.chars().count() as i32);
+        y += 1;
+    }
+    pattern.height = y;
+    Ok(pattern)
+}
+
+/// Writes a two-state pattern in the plaintext format, rows padded with `.`
+/// to the pattern's width.
+pub fn write(pattern: &Pattern) -> Result<String, PatternError> {
+    pattern.check_two_state()?;
+    let mut out = String::new();
+    if let Some(name) = &pattern.name {
+        writeln!(out, "!Name: {}", name).unwrap();
+    }
+    if let Some(author) = &pattern.author {
+        writeln!(out, "!Author: {}", author).unwrap();
+    }
+    for comment in &pattern.comments {
+        writeln!(out, "!{}", comment).unwrap();
+    }
+    for y in 0..pattern.height {
+        let row: String = (0..pattern.width)
+            .map(|x| if pattern.cells.contains_key(&(x, y)) { 'O' } else { '.' })
+            .collect();
+        writeln!(out, "{}", row).unwrap();
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GLIDER: &str = "!Name: Glider
+!Author: Richard K. Guy
+!The smallest spaceship.
+.O.
+..O
+OOO
+";
+
+    #[test]
+    fn test_parse_glider() {
+        let pattern = parse(GLIDER).unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("Glider"));
+        assert_eq!(pattern.author.as_deref(), Some("Richard K. Guy"));
+        assert_eq!(pattern.comments, vec!["The smallest spaceship."]);
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        assert_eq!(pattern.cells.keys().copied().collect::<Vec<_>>(), vec![(0, 2), (1, 0), (1, 2), (2, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_parse_short_rows_and_stars() {
+        let pattern = parse("*\n\n....*").unwrap();
+        assert_eq!((pattern.width, pattern.height), (5, 3));
+        assert_eq!(pattern.cells.len(), 2);
+        assert_eq!(pattern.cells.get(&(4, 2)), Some(&1));
+        assert_eq!(parse("..x"), Err(PatternError::UnexpectedCharacter('x')));
+    }
+
+    #[test]
+    fn test_write_round_trips() {
+        let pattern = parse(GLIDER).unwrap();
+        assert_eq!(write(&pattern).unwrap(), GLIDER);
+        assert_eq!(parse(&write(&pattern).unwrap()), Ok(pattern));
+    }
+
+    #[test]
+    fn test_write_rejects_multi_state_cells() {
+        let mut pattern = parse(GLIDER).unwrap();
+        pattern.cells.insert((0, 0), 2);
+        assert_eq!(write(&pattern), Err(PatternError::MultiState(2)));
+    }
+}
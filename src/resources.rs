@@ -1,207 +1,1061 @@
-pub type RuleFn = fn(bool, i32) -> bool;
-
-pub fn parse_rule(rule_str: &str) -> RuleFn {
-    let parts: Vec<&str> = rule_str.split('s').collect();
-    let born_part: Vec<&str> = parts[0].matches(char::is_numeric).collect();
-    let stay_part: Vec<&str> = parts[1].matches(char::is_numeric).collect();
-
-    let born_keys: Vec<i32> = born_part
-        .iter()
-        .map(|&each| each.parse::<i32>().unwrap())
-        .collect();
-    let stay_keys: Vec<i32> = stay_part
-        .iter()
-        .map(|&each| each.parse::<i32>().unwrap())
-        .collect();
-    
-    make_rule(born_keys, stay_keys)
-}
-
-fn make_rule(borns: Vec<i32>, stays: Vec<i32>) -> RuleFn {
-    if borns == vec![3] && stays == vec![2, 3] {
-        conway_rule
-    } else if borns == vec![3, 6] && stays == vec![2, 3] {
-        highlife_rule
-    } else if borns == vec![3, 6, 7, 8] && stays == vec![3, 4, 6, 7, 8] {
-        day_and_night_rule
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::hensel;
+use crate::ltl::{self, Ltl};
+use crate::map;
+use crate::named_rules::{self, NamedRule};
+use crate::neighbourhood::Neighbourhood;
+use crate::topology::{BoundedGrid, TopologyParseError};
+use crate::universe::Simulation;
+
+pub use crate::topology::Topology;
+
+/// The bit of a 3x3 neighbourhood index for the cell at offset `(dx, dy)`
+/// from the centre: bit 8 is the north-west neighbour, bit 4 the cell
+/// itself and bit 0 the south-east neighbour.
+pub const fn neighbour_bit(dx: i32, dy: i32) -> u16 {
+    1 << (4 - dx - 3 * dy)
+}
+
+/// The neighbourhood index bit of the cell itself.
+pub const CENTRE: u16 = neighbour_bit(0, 0);
+
+/// The set of 3x3 neighbourhood indices, centre included, after which a
+/// cell is alive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Transitions([u64; 8]);
+
+impl Transitions {
+    pub fn contains(&self, index: u16) -> bool {
+        self.0[index as usize / 64] & (1 << (index % 64)) != 0
+    }
+
+    pub fn insert(&mut self, index: u16) {
+        self.0[index as usize / 64] |= 1 << (index % 64);
+    }
+}
+
+/// A Life-like rule in B/S notation.
+///
+/// Bit `n` of `birth` is set when a dead cell with `n` live neighbours is
+/// born, bit `n` of `survive` when a live cell with `n` live neighbours
+/// stays alive, counting the cells of `neighbourhood`. Non-totalistic
+/// rules also have `transitions`, which tell apart the neighbourhoods of
+/// each count; their `birth` and `survive` bits are set for the counts
+/// where any neighbourhood qualifies. Rules with more than two `states`
+/// are Generations rules: a live cell that does not survive goes through
+/// `states - 2` dying states before it is dead, and only live cells count
+/// as neighbours. Larger than Life rules have `ltl`, whose ranges and
+/// neighbourhood take the place of the other fields, and multi-state rules
+/// such as Wireworld are `named`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rule {
+    pub birth: u64,
+    pub survive: u64,
+    pub states: u8,
+    pub transitions: Option<Transitions>,
+    pub neighbourhood: Neighbourhood,
+    pub ltl: Option<Ltl>,
+    pub named: Option<&'static NamedRule>,
+}
+
+/// Which of a rule's fields decide its next states.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleKind {
+    /// `birth` and `survive` counts over `neighbourhood`.
+    Totalistic,
+    /// A table of 3x3 neighbourhoods in `transitions`.
+    NonTotalistic,
+    /// The ranges and neighbourhood of `ltl`.
+    LargerThanLife,
+    /// The function of a `named` rule.
+    Named,
+}
+
+impl Rule {
+    pub fn new(borns: &[i32], stays: &[i32]) -> Self {
+        Rule::generations(borns, stays, 2)
+    }
+
+    pub fn generations(borns: &[i32], stays: &[i32], states: u8) -> Self {
+        Rule {
+            birth: to_mask(borns),
+            survive: to_mask(stays),
+            states,
+            transitions: None,
+            neighbourhood: Neighbourhood::default(),
+            ltl: None,
+            named: None,
+        }
+    }
+
+    pub fn larger_than_life(ltl: Ltl, states: u8) -> Self {
+        Rule {
+            ltl: Some(ltl),
+            ..Rule::generations(&[], &[], states)
+        }
+    }
+
+    pub fn named(rule: &'static NamedRule) -> Self {
+        Rule {
+            named: Some(rule),
+            neighbourhood: rule.neighbourhood,
+            ..Rule::generations(&[], &[], rule.states)
+        }
+    }
+
+    pub fn with_neighbourhood(mut self, neighbourhood: Neighbourhood) -> Self {
+        self.neighbourhood = neighbourhood;
+        self
+    }
+
+    /// The rule that looks each neighbourhood up in `table`. A table that
+    /// only depends on the live count gives the plain outer-totalistic rule.
+    pub fn from_transitions(table: Transitions, states: u8) -> Self {
+        let mut rule = Rule {
+            birth: 0,
+            survive: 0,
+            states,
+            transitions: None,
+            neighbourhood: Neighbourhood::default(),
+            ltl: None,
+            named: None,
+        };
+        let mut totalistic = true;
+        for count in 0..=8 {
+            let indices = || (0..0x200u16).filter(move |index| index & CENTRE == 0 && index.count_ones() == count);
+            let born = indices().filter(|&index| table.contains(index)).count();
+            let survived = indices().filter(|&index| table.contains(index | CENTRE)).count();
+            let total = indices().count();
+            totalistic &= (born == 0 || born == total) && (survived == 0 || survived == total);
+            if born > 0 {
+                rule.birth |= 1 << count;
+            }
+            if survived > 0 {
+                rule.survive |= 1 << count;
+            }
+        }
+        if !totalistic {
+            rule.transitions = Some(table);
+        }
+        rule
+    }
+
+    pub fn conway() -> Self {
+        Rule::new(&[3], &[2, 3])
+    }
+
+    pub fn highlife() -> Self {
+        Rule::new(&[3, 6], &[2, 3])
+    }
+
+    pub fn day_and_night() -> Self {
+        Rule::new(&[3, 6, 7, 8], &[3, 4, 6, 7, 8])
+    }
+
+    /// Whether a dead cell with no live neighbours is born, as in B0 rules.
+    pub fn has_b0(&self) -> bool {
+        self.next_state(0, 0) != 0
+    }
+
+    pub fn kind(&self) -> RuleKind {
+        match (self.named, self.ltl, self.transitions) {
+            (Some(_), _, _) => RuleKind::Named,
+            (None, Some(_), _) => RuleKind::LargerThanLife,
+            (None, None, Some(_)) => RuleKind::NonTotalistic,
+            (None, None, None) => RuleKind::Totalistic,
+        }
+    }
+
+    /// How far the neighbourhood reaches from the cell.
+    pub fn radius(&self) -> i32 {
+        self.ltl.map_or(self.neighbourhood.radius(), |ltl| ltl.radius as i32)
+    }
+
+    /// The offsets of the cells whose live count decides a cell's next
+    /// state.
+    pub fn offsets(&self) -> Vec<(i32, i32)> {
+        self.ltl.map_or_else(|| self.neighbourhood.offsets(), |ltl| ltl.offsets())
+    }
+
+    /// Returns the state that follows `curr_state` given the number of live
+    /// neighbours: 0 is dead, 1 is alive and anything above is dying.
+    pub fn next_state(&self, curr_state: u8, neighbours_alive: i32) -> u8 {
+        let counted = |mask: u64| (0..64).contains(&neighbours_alive) && mask & (1 << neighbours_alive) != 0;
+        let (born, survives) = match &self.ltl {
+            Some(ltl) => (ltl.born(neighbours_alive), ltl.survives(neighbours_alive)),
+            None => (counted(self.birth), counted(self.survive)),
+        };
+        match curr_state {
+            0 if born => 1,
+            0 => 0,
+            1 if survives => 1,
+            dying if dying < self.states - 1 => dying + 1,
+            _ => 0,
+        }
+    }
+
+    /// The 3x3 neighbourhoods after which a live or dead cell is alive,
+    /// whether the rule was given as a table or by counts, or `None` for
+    /// neighbourhoods reaching further than the 3x3 square and for named
+    /// rules.
+    pub fn transition_table(&self) -> Option<Transitions> {
+        if let Some(table) = self.transitions {
+            return Some(table);
+        }
+        if self.radius() > 1 || self.named.is_some() {
+            return None;
+        }
+        let offsets = self.offsets();
+        let mut table = Transitions::default();
+        for index in 0..0x200u16 {
+            let count = offsets.iter().filter(|&&(dx, dy)| index & neighbour_bit(dx, dy) != 0).count();
+            if self.next_state((index & CENTRE != 0) as u8, count as i32) == 1 {
+                table.insert(index);
+            }
+        }
+        Some(table)
+    }
+
+    /// The rule as a MAP string, with a `/` and state count for
+    /// Generations rules, or `None` for neighbourhoods a MAP string cannot
+    /// hold.
+    pub fn to_map(&self) -> Option<String> {
+        let mut out = format!("{}{}", map::PREFIX, map::encode(&self.transition_table()?));
+        if self.states > 2 {
+            out.push_str(&format!("/{}", self.states));
+        }
+        Some(out)
+    }
+
+    /// Returns the state that follows `curr_state` given its 3x3
+    /// neighbourhood, with bits from `neighbour_bit` set for the live
+    /// neighbours. The centre bit is ignored. Only for rules whose
+    /// neighbourhood fits in the 3x3 square.
+    pub fn next_state_for(&self, curr_state: u8, neighbourhood: u16) -> u8 {
+        let table = match &self.transitions {
+            Some(table) => table,
+            None if self.neighbourhood == Neighbourhood::Moore(1) => {
+                return self.next_state(curr_state, (neighbourhood & !CENTRE).count_ones() as i32)
+            }
+            None => return self.next_state(curr_state, (neighbourhood & self.neighbourhood.mask()).count_ones() as i32),
+        };
+        let index = neighbourhood & !CENTRE;
+        match curr_state {
+            0 => table.contains(index) as u8,
+            1 if table.contains(index | CENTRE) => 1,
+            dying if dying < self.states - 1 => dying + 1,
+            _ => 0,
+        }
+    }
+}
+
+fn to_mask(counts: &[i32]) -> u64 {
+    counts.iter().fold(0, |mask, &count| mask | (1 << count))
+}
+
+/// Writes the counts in `mask` as digits, or separated by commas when the
+/// neighbourhood has more than 9 cells.
+fn write_counts(f: &mut fmt::Formatter, mask: u64, size: usize) -> fmt::Result {
+    let counts: Vec<String> =
+        (0..=size).filter(|count| mask & (1 << count) != 0).map(|count| count.to_string()).collect();
+    write!(f, "{}", counts.join(if size > 9 { "," } else { "" }))
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ltl) = &self.ltl {
+            return write!(f, "{}", ltl::write(ltl, self.states));
+        }
+        if let Some(named) = self.named {
+            return write!(f, "{}", named.name);
+        }
+        match &self.transitions {
+            Some(table) if !hensel::is_isotropic(table) => return write!(f, "{}", self.to_map().unwrap_or_default()),
+            Some(table) => write!(f, "B{}/S{}", hensel::write_section(table, false), hensel::write_section(table, true))?,
+            None => {
+                let size = self.neighbourhood.size();
+                write!(f, "B")?;
+                write_counts(f, self.birth, size)?;
+                write!(f, "/S")?;
+                write_counts(f, self.survive, size)?;
+                write!(f, "{}", self.neighbourhood)?;
+            }
+        }
+        if self.states > 2 {
+            write!(f, "/C{}", self.states)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = RuleParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_rule(s)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleParseError {
+    Empty,
+    UnexpectedCharacter(char),
+    CountOutOfRange(char),
+    MissingSection(char),
+    DuplicateSection(char),
+    InvalidStateCount(String),
+    InvalidTopology(TopologyParseError),
+    InvalidNeighbourhood(String),
+    InvalidMap(String),
+    InvalidNeighbourhoodShape(String),
+    InvalidCount(String),
+    InvalidRange(String),
+    InvalidRadius(String),
+    InvalidField(String),
+}
+
+impl fmt::Display for RuleParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleParseError::Empty => write!(f, "rule string is empty"),
+            RuleParseError::UnexpectedCharacter(c) => write!(f, "unexpected character '{}' in rule", c),
+            RuleParseError::CountOutOfRange(c) => write!(f, "neighbour count {} is more than the neighbourhood has", c),
+            RuleParseError::MissingSection(c) => write!(f, "rule has no '{}' section", c),
+            RuleParseError::DuplicateSection(c) => write!(f, "rule has more than one '{}' section", c),
+            RuleParseError::InvalidStateCount(s) => write!(f, "state count '{}' is not a number in 2-255", s),
+            RuleParseError::InvalidTopology(e) => write!(f, "{}", e),
+            RuleParseError::InvalidNeighbourhood(s) => write!(f, "invalid neighbourhood group '{}'", s),
+            RuleParseError::InvalidMap(s) => write!(f, "invalid MAP rule table '{}'", s),
+            RuleParseError::InvalidNeighbourhoodShape(s) => write!(f, "unknown neighbourhood '{}'", s),
+            RuleParseError::InvalidCount(s) => write!(f, "neighbour count '{}' is out of range for the neighbourhood", s),
+            RuleParseError::InvalidRange(s) => write!(f, "invalid count range '{}' for the neighbourhood", s),
+            RuleParseError::InvalidRadius(s) => write!(f, "radius '{}' is not a number in 1-{}", s, ltl::MAX_RADIUS),
+            RuleParseError::InvalidField(s) => write!(f, "invalid rule field '{}'", s),
+        }
+    }
+}
+
+impl Error for RuleParseError {}
+
+/// Parses a Life-like or Generations rule.
+///
+/// Accepts B/S notation in any case and section order, with or without a
+/// slash (`B3/S23`, `b3s23`, `S23B3`), and the classic S/B notation
+/// (`23/3`). Generations rules add a state count, either as a `C` section
+/// (`B2/S/C3`) or as a third S/B field (`/2/3`). Isotropic non-totalistic
+/// rules follow a count with Hensel letters for the neighbourhoods it
+/// takes (`B2ce`) or, after a `-`, leaves out (`B2-a/S12`); in a rule
+/// without slashes a trailing `c` and digits is the state count. MAP rules
+/// give the whole 512-entry neighbourhood table in base64, optionally
+/// followed by `/` and a state count. Larger than Life rules are written
+/// as in Golly, `R5,C0,M1,S34..58,B34..45,NM`. Named rules such as
+/// `WireWorld` are looked up in `named_rules::RULES`.
+pub fn parse_rule(rule_str: &str) -> Result<Rule, RuleParseError> {
+    let rule_str = rule_str.trim();
+    if rule_str.is_empty() {
+        return Err(RuleParseError::Empty);
+    }
+
+    if let Some(named) = named_rules::find(rule_str) {
+        Ok(Rule::named(named))
+    } else if let Some(body) = rule_str.strip_prefix(map::PREFIX) {
+        parse_map(body)
+    } else if rule_str.starts_with(['R', 'r']) {
+        let (ltl, states) = ltl::parse(rule_str)?;
+        Ok(Rule::larger_than_life(ltl, states))
+    } else if rule_str.contains(|c: char| c.is_ascii_alphabetic()) {
+        parse_bs_notation(rule_str)
     } else {
-        conway_rule
+        parse_sb_notation(rule_str)
     }
 }
 
-pub fn conway_rule(curr_state: bool, neighbours_alive: i32) -> bool {
-    match curr_state {
-        true if [2, 3].contains(&neighbours_alive) => true,
-        false if neighbours_alive == 3 => true,
-        _ => false,
+/// Parses a rule with an optional bounded grid suffix, as in
+/// `B3/S23:T80,50`.
+pub fn parse_rule_spec(spec: &str) -> Result<(Rule, Option<BoundedGrid>), RuleParseError> {
+    match spec.split_once(':') {
+        Some((rule_str, grid_str)) => {
+            let grid = grid_str.parse().map_err(RuleParseError::InvalidTopology)?;
+            Ok((parse_rule(rule_str)?, Some(grid)))
+        }
+        None => Ok((parse_rule(spec)?, None)),
     }
 }
 
-pub fn highlife_rule(curr_state: bool, neighbours_alive: i32) -> bool {
-    match curr_state {
-        true if [2, 3].contains(&neighbours_alive) => true,
-        false if [3, 6].contains(&neighbours_alive) => true,
-        _ => false,
+fn parse_bs_notation(rule_str: &str) -> Result<Rule, RuleParseError> {
+    const LABELS: [char; 3] = ['B', 'S', 'C'];
+    const SHAPES: [char; 4] = ['M', 'V', 'H', '@'];
+    let mut sections: [Option<String>; 4] = [None, None, None, None];
+    let mut current: Option<usize> = None;
+    let mut after_separator = false;
+
+    // Without slashes, a trailing `c` and digits is the state count rather
+    // than a Hensel letter and more counts, so `b3s23c3` has three states.
+    let states_at = rule_str
+        .rfind(['c', 'C'])
+        .filter(|&at| !rule_str.contains('/') && rule_str[at + 1..].parse::<u8>().is_ok());
+    for (at, c) in rule_str.char_indices() {
+        let upper = c.to_ascii_uppercase();
+        let section_text = current.filter(|_| !after_separator).and_then(|section| sections[section].as_ref());
+        let in_counts = current.is_some_and(|section| section < 2) && section_text.is_some_and(|text| !text.is_empty());
+        let in_custom = current == Some(3) && section_text.is_some_and(|text| text.starts_with('@'));
+        let in_letters = in_counts && (c == '-' || c == ',' || hensel::ALPHABET.contains(c)) && Some(at) != states_at;
+        if in_letters || (in_custom && c.is_ascii_hexdigit()) {
+            if let Some(text) = current.and_then(|section| sections[section].as_mut()) {
+                text.push(c);
+            }
+        } else if let Some(section) = LABELS.iter().position(|&label| label == upper) {
+            if sections[section].is_some() {
+                return Err(RuleParseError::DuplicateSection(upper));
+            }
+            sections[section] = Some(String::new());
+            current = Some(section);
+            after_separator = false;
+        } else if SHAPES.contains(&upper) {
+            if sections[3].is_some() {
+                return Err(RuleParseError::DuplicateSection(upper));
+            }
+            sections[3] = Some(upper.to_string());
+            current = Some(3);
+            after_separator = false;
+        } else if c == '/' && current.is_some() && !after_separator {
+            after_separator = true;
+        } else if let (true, Some(section), false) = (c.is_ascii_digit(), current, after_separator) {
+            if let Some(digits) = sections[section].as_mut() {
+                digits.push(c);
+            }
+        } else {
+            return Err(RuleParseError::UnexpectedCharacter(c));
+        }
     }
+
+    if after_separator {
+        return Err(RuleParseError::UnexpectedCharacter('/'));
+    }
+
+    let [borns, stays, states, shape] = sections;
+    let borns = borns.ok_or(RuleParseError::MissingSection('B'))?;
+    let stays = stays.ok_or(RuleParseError::MissingSection('S'))?;
+    let states = states.map_or(Ok(2), |digits| parse_states(&digits))?;
+    let neighbourhood: Neighbourhood = shape.map_or(Ok(Neighbourhood::default()), |shape| shape.parse())?;
+    let letters = |counts: &String| counts.chars().find(|&c| c == '-' || c.is_ascii_alphabetic());
+    if let Some(letter) = letters(&borns).or_else(|| letters(&stays)) {
+        if neighbourhood != Neighbourhood::default() {
+            return Err(RuleParseError::UnexpectedCharacter(letter));
+        }
+        let mut table = Transitions::default();
+        hensel::parse_section(&borns, false, &mut table)?;
+        hensel::parse_section(&stays, true, &mut table)?;
+        return Ok(Rule::from_transitions(table, states));
+    }
+    let size = neighbourhood.size();
+    Ok(Rule {
+        birth: parse_counts(&borns, size)?,
+        survive: parse_counts(&stays, size)?,
+        states,
+        transitions: None,
+        neighbourhood,
+        ltl: None,
+        named: None,
+    })
 }
 
-pub fn day_and_night_rule(curr_state: bool, neighbours_alive: i32) -> bool {
-    match curr_state {
-        true if [3, 4, 6, 7, 8].contains(&neighbours_alive) => true,
-        false if [3, 6, 7, 8].contains(&neighbours_alive) => true,
-        _ => false,
+fn parse_map(body: &str) -> Result<Rule, RuleParseError> {
+    if !body.is_ascii() {
+        return Err(RuleParseError::InvalidMap(body.to_string()));
     }
+    let (table, rest) = body.split_at(body.len().min(map::LENGTH));
+    let rest = rest.strip_prefix("==").unwrap_or(rest);
+    let states = match rest.strip_prefix('/') {
+        Some(states) => parse_states(states)?,
+        None if rest.is_empty() => 2,
+        None => return Err(RuleParseError::InvalidMap(body.to_string())),
+    };
+    Ok(Rule::from_transitions(map::decode(table)?, states))
 }
 
+fn parse_sb_notation(rule_str: &str) -> Result<Rule, RuleParseError> {
+    let mut parts = rule_str.split('/');
+    let stays = parts.next().unwrap_or_default();
+    let borns = parts.next().ok_or(RuleParseError::MissingSection('B'))?;
+    let states = parts.next().map_or(Ok(2), parse_states)?;
+    if parts.next().is_some() {
+        return Err(RuleParseError::UnexpectedCharacter('/'));
+    }
+
+    Ok(Rule {
+        birth: parse_counts(borns, 8)?,
+        survive: parse_counts(stays, 8)?,
+        states,
+        transitions: None,
+        neighbourhood: Neighbourhood::default(),
+        ltl: None,
+        named: None,
+    })
+}
+
+fn parse_states(digits: &str) -> Result<u8, RuleParseError> {
+    match digits.parse::<u8>() {
+        Ok(states) if states >= 2 => Ok(states),
+        _ => Err(RuleParseError::InvalidStateCount(digits.to_string())),
+    }
+}
+
+/// Parses the counts of a B or S section for a neighbourhood of `size`
+/// cells: one digit per count, or numbers separated by commas when the
+/// neighbourhood has more than 9 cells.
+fn parse_counts(counts: &str, size: usize) -> Result<u64, RuleParseError> {
+    if size > 9 {
+        return counts.split(',').filter(|count| !count.is_empty()).try_fold(0, |mask, count| {
+            match count.parse::<usize>() {
+                Ok(count) if count <= size => Ok(mask | 1 << count),
+                _ => Err(RuleParseError::InvalidCount(count.to_string())),
+            }
+        });
+    }
+    counts.chars().try_fold(0, |mask, c| {
+        if c.is_ascii_digit() {
+            Ok(mask | count_bit(c, size)?)
+        } else {
+            Err(RuleParseError::UnexpectedCharacter(c))
+        }
+    })
+}
+
+fn count_bit(digit: char, size: usize) -> Result<u64, RuleParseError> {
+    match digit.to_digit(10) {
+        Some(count) if count as usize <= size => Ok(1 << count),
+        _ => Err(RuleParseError::CountOutOfRange(digit)),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Dimensions {
     pub width: i32,
     pub height: i32,
 }
 
-use std::collections::HashMap;
-use legion::Entity;
-
+#[derive(Default)]
 pub struct InputState {
     pub mouse_pos: (i32, i32),
     pub mouse_left: bool,
 }
 
-impl Default for InputState {
-    fn default() -> Self {
-        InputState {
-            mouse_pos: (0, 0),
-            mouse_left: false,
-        }
-    }
-}
+/// The simulation the systems step and mirror into the cell entities.
+pub struct Engine(pub Box<dyn Simulation>);
 
-pub struct PositionIndex(pub HashMap<(i32, i32), Entity>);
+/// Position on the unbounded plane shown at the top-left corner of the
+/// window.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: i64,
+    pub y: i64,
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::neighbourhood::Neighbourhood;
 
     #[test]
     fn test_conway_rule_live_cell_survives_with_2_neighbors() {
-        assert_eq!(conway_rule(true, 2), true);
+        assert_eq!(Rule::conway().next_state(1, 2), 1);
     }
 
     #[test]
     fn test_conway_rule_live_cell_survives_with_3_neighbors() {
-        assert_eq!(conway_rule(true, 3), true);
+        assert_eq!(Rule::conway().next_state(1, 3), 1);
     }
 
     #[test]
     fn test_conway_rule_live_cell_dies_with_fewer_than_2_neighbors() {
-        assert_eq!(conway_rule(true, 0), false);
-        assert_eq!(conway_rule(true, 1), false);
+        assert_eq!(Rule::conway().next_state(1, 0), 0);
+        assert_eq!(Rule::conway().next_state(1, 1), 0);
     }
 
     #[test]
     fn test_conway_rule_live_cell_dies_with_more_than_3_neighbors() {
-        assert_eq!(conway_rule(true, 4), false);
-        assert_eq!(conway_rule(true, 5), false);
-        assert_eq!(conway_rule(true, 8), false);
+        assert_eq!(Rule::conway().next_state(1, 4), 0);
+        assert_eq!(Rule::conway().next_state(1, 5), 0);
+        assert_eq!(Rule::conway().next_state(1, 8), 0);
     }
 
     #[test]
     fn test_conway_rule_dead_cell_becomes_alive_with_3_neighbors() {
-        assert_eq!(conway_rule(false, 3), true);
+        assert_eq!(Rule::conway().next_state(0, 3), 1);
     }
 
     #[test]
     fn test_conway_rule_dead_cell_stays_dead() {
-        assert_eq!(conway_rule(false, 0), false);
-        assert_eq!(conway_rule(false, 1), false);
-        assert_eq!(conway_rule(false, 2), false);
-        assert_eq!(conway_rule(false, 4), false);
+        assert_eq!(Rule::conway().next_state(0, 0), 0);
+        assert_eq!(Rule::conway().next_state(0, 1), 0);
+        assert_eq!(Rule::conway().next_state(0, 2), 0);
+        assert_eq!(Rule::conway().next_state(0, 4), 0);
     }
 
     #[test]
     fn test_highlife_rule_birth_with_3_neighbors() {
-        assert_eq!(highlife_rule(false, 3), true);
+        assert_eq!(Rule::highlife().next_state(0, 3), 1);
     }
 
     #[test]
     fn test_highlife_rule_birth_with_6_neighbors() {
-        assert_eq!(highlife_rule(false, 6), true);
+        assert_eq!(Rule::highlife().next_state(0, 6), 1);
     }
 
     #[test]
     fn test_highlife_rule_survive_with_2_neighbors() {
-        assert_eq!(highlife_rule(true, 2), true);
+        assert_eq!(Rule::highlife().next_state(1, 2), 1);
     }
 
     #[test]
     fn test_highlife_rule_survive_with_3_neighbors() {
-        assert_eq!(highlife_rule(true, 3), true);
+        assert_eq!(Rule::highlife().next_state(1, 3), 1);
     }
 
     #[test]
     fn test_highlife_rule_death() {
-        assert_eq!(highlife_rule(true, 0), false);
-        assert_eq!(highlife_rule(true, 1), false);
-        assert_eq!(highlife_rule(true, 4), false);
-        assert_eq!(highlife_rule(false, 2), false);
-        assert_eq!(highlife_rule(false, 5), false);
+        assert_eq!(Rule::highlife().next_state(1, 0), 0);
+        assert_eq!(Rule::highlife().next_state(1, 1), 0);
+        assert_eq!(Rule::highlife().next_state(1, 4), 0);
+        assert_eq!(Rule::highlife().next_state(0, 2), 0);
+        assert_eq!(Rule::highlife().next_state(0, 5), 0);
     }
 
     #[test]
     fn test_day_and_night_rule_birth() {
-        assert_eq!(day_and_night_rule(false, 3), true);
-        assert_eq!(day_and_night_rule(false, 6), true);
-        assert_eq!(day_and_night_rule(false, 7), true);
-        assert_eq!(day_and_night_rule(false, 8), true);
+        assert_eq!(Rule::day_and_night().next_state(0, 3), 1);
+        assert_eq!(Rule::day_and_night().next_state(0, 6), 1);
+        assert_eq!(Rule::day_and_night().next_state(0, 7), 1);
+        assert_eq!(Rule::day_and_night().next_state(0, 8), 1);
     }
 
     #[test]
     fn test_day_and_night_rule_survive() {
-        assert_eq!(day_and_night_rule(true, 3), true);
-        assert_eq!(day_and_night_rule(true, 4), true);
-        assert_eq!(day_and_night_rule(true, 6), true);
-        assert_eq!(day_and_night_rule(true, 7), true);
-        assert_eq!(day_and_night_rule(true, 8), true);
+        assert_eq!(Rule::day_and_night().next_state(1, 3), 1);
+        assert_eq!(Rule::day_and_night().next_state(1, 4), 1);
+        assert_eq!(Rule::day_and_night().next_state(1, 6), 1);
+        assert_eq!(Rule::day_and_night().next_state(1, 7), 1);
+        assert_eq!(Rule::day_and_night().next_state(1, 8), 1);
     }
 
     #[test]
     fn test_day_and_night_rule_death() {
-        assert_eq!(day_and_night_rule(true, 0), false);
-        assert_eq!(day_and_night_rule(true, 1), false);
-        assert_eq!(day_and_night_rule(true, 2), false);
-        assert_eq!(day_and_night_rule(true, 5), false);
-        assert_eq!(day_and_night_rule(false, 0), false);
-        assert_eq!(day_and_night_rule(false, 2), false);
-        assert_eq!(day_and_night_rule(false, 5), false);
+        assert_eq!(Rule::day_and_night().next_state(1, 0), 0);
+        assert_eq!(Rule::day_and_night().next_state(1, 1), 0);
+        assert_eq!(Rule::day_and_night().next_state(1, 2), 0);
+        assert_eq!(Rule::day_and_night().next_state(1, 5), 0);
+        assert_eq!(Rule::day_and_night().next_state(0, 0), 0);
+        assert_eq!(Rule::day_and_night().next_state(0, 2), 0);
+        assert_eq!(Rule::day_and_night().next_state(0, 5), 0);
     }
 
     #[test]
     fn test_parse_rule_conway() {
-        let rule = parse_rule("b3s23");
-        assert_eq!(rule(true, 2), true);
-        assert_eq!(rule(true, 3), true);
-        assert_eq!(rule(false, 3), true);
-        assert_eq!(rule(true, 4), false);
+        let rule = parse_rule("b3s23").unwrap();
+        assert_eq!(rule.next_state(1, 2), 1);
+        assert_eq!(rule.next_state(1, 3), 1);
+        assert_eq!(rule.next_state(0, 3), 1);
+        assert_eq!(rule.next_state(1, 4), 0);
     }
 
     #[test]
     fn test_parse_rule_highlife() {
-        let rule = parse_rule("b36s23");
-        assert_eq!(rule(false, 6), true);
-        assert_eq!(rule(false, 3), true);
-        assert_eq!(rule(true, 2), true);
+        let rule = parse_rule("b36s23").unwrap();
+        assert_eq!(rule.next_state(0, 6), 1);
+        assert_eq!(rule.next_state(0, 3), 1);
+        assert_eq!(rule.next_state(1, 2), 1);
     }
 
     #[test]
     fn test_parse_rule_day_and_night() {
-        let rule = parse_rule("b3678s34678");
-        assert_eq!(rule(false, 8), true);
-        assert_eq!(rule(true, 4), true);
+        let rule = parse_rule("b3678s34678").unwrap();
+        assert_eq!(rule.next_state(0, 8), 1);
+        assert_eq!(rule.next_state(1, 4), 1);
+    }
+
+    #[test]
+    fn test_parse_rule_arbitrary_rule_is_not_conway() {
+        let rule = parse_rule("b45s67").unwrap();
+        assert_eq!(rule.next_state(0, 4), 1);
+        assert_eq!(rule.next_state(0, 5), 1);
+        assert_eq!(rule.next_state(1, 6), 1);
+        assert_eq!(rule.next_state(1, 2), 0);
+        assert_eq!(rule.next_state(0, 3), 0);
+    }
+
+    #[test]
+    fn test_parse_rule_seeds() {
+        let rule = parse_rule("b2s").unwrap();
+        assert_eq!(rule.next_state(0, 2), 1);
+        for n in 0..=8 {
+            assert_eq!(rule.next_state(1, n), 0);
+        }
+    }
+
+    #[test]
+    fn test_parse_rule_life_without_death() {
+        let rule = parse_rule("b3s012345678").unwrap();
+        for n in 0..=8 {
+            assert_eq!(rule.next_state(1, n), 1);
+        }
+        assert_eq!(rule.next_state(0, 3), 1);
+        assert_eq!(rule.next_state(0, 2), 0);
+    }
+
+    #[test]
+    fn test_parse_rule_maze() {
+        let rule = parse_rule("b3s12345").unwrap();
+        assert_eq!(rule.next_state(1, 1), 1);
+        assert_eq!(rule.next_state(1, 5), 1);
+        assert_eq!(rule.next_state(1, 6), 0);
+        assert_eq!(rule.next_state(1, 0), 0);
+    }
+
+    #[test]
+    fn test_rule_constructors_match_parsed_rules() {
+        assert_eq!(parse_rule("b3s23").unwrap(), Rule::conway());
+        assert_eq!(parse_rule("b36s23").unwrap(), Rule::highlife());
+        assert_eq!(parse_rule("b3678s34678").unwrap(), Rule::day_and_night());
+    }
+
+    #[test]
+    fn test_parse_rule_accepts_all_conway_notations() {
+        for notation in ["B3/S23", "b3s23", "B3S23", "b3/s23", "S23/B3", "s23b3", "23/3", " B3/S23 "] {
+            assert_eq!(parse_rule(notation), Ok(Rule::conway()), "{}", notation);
+        }
+    }
+
+    #[test]
+    fn test_parse_rule_empty_sections() {
+        let seeds = Rule::new(&[2], &[]);
+        assert_eq!(parse_rule("B2/S"), Ok(seeds));
+        assert_eq!(parse_rule("/2"), Ok(seeds));
+        assert_eq!(parse_rule("B/S"), Ok(Rule::new(&[], &[])));
+    }
+
+    #[test]
+    fn test_parse_rule_errors() {
+        assert_eq!(parse_rule(""), Err(RuleParseError::Empty));
+        assert_eq!(parse_rule("b3"), Err(RuleParseError::MissingSection('S')));
+        assert_eq!(parse_rule("23"), Err(RuleParseError::MissingSection('B')));
+        assert_eq!(parse_rule("B9/S23"), Err(RuleParseError::CountOutOfRange('9')));
+        assert_eq!(parse_rule("23/39"), Err(RuleParseError::CountOutOfRange('9')));
+        assert_eq!(parse_rule("B3/S23x"), Err(RuleParseError::UnexpectedCharacter('x')));
+        assert_eq!(parse_rule("3/S23"), Err(RuleParseError::UnexpectedCharacter('3')));
+        assert_eq!(parse_rule("B3//S23"), Err(RuleParseError::UnexpectedCharacter('/')));
+        assert_eq!(parse_rule("B3/S23/"), Err(RuleParseError::UnexpectedCharacter('/')));
+        assert_eq!(parse_rule("B3/S2/3"), Err(RuleParseError::UnexpectedCharacter('3')));
+        assert_eq!(parse_rule("23/3/4/"), Err(RuleParseError::UnexpectedCharacter('/')));
+        assert_eq!(parse_rule("B3b6/S23"), Err(RuleParseError::DuplicateSection('B')));
+    }
+
+    #[test]
+    fn test_rule_display_is_canonical() {
+        assert_eq!(Rule::conway().to_string(), "B3/S23");
+        assert_eq!(parse_rule("s8763b3").unwrap().to_string(), "B3/S3678");
+        assert_eq!(parse_rule("/2").unwrap().to_string(), "B2/S");
+    }
+
+    #[test]
+    fn test_rule_from_str_round_trips() {
+        let rule: Rule = "34678/3678".parse().unwrap();
+        assert_eq!(rule, Rule::day_and_night());
+        assert_eq!(rule.to_string().parse::<Rule>(), Ok(rule));
+    }
+
+    #[test]
+    fn test_generations_rule_decays_through_dying_states() {
+        let rule = Rule::generations(&[2], &[], 4);
+        assert_eq!(rule.next_state(0, 2), 1);
+        assert_eq!(rule.next_state(1, 2), 2);
+        assert_eq!(rule.next_state(2, 2), 3);
+        assert_eq!(rule.next_state(3, 2), 0);
+    }
+
+    #[test]
+    fn test_generations_rule_live_cell_survives() {
+        let star_wars = parse_rule("345/2/4").unwrap();
+        assert_eq!(star_wars.next_state(1, 4), 1);
+        assert_eq!(star_wars.next_state(1, 2), 2);
+    }
+
+    #[test]
+    fn test_generations_dying_cell_ignores_neighbours() {
+        let rule = parse_rule("B2/S/C3").unwrap();
+        assert_eq!(rule.next_state(2, 2), 0);
+        assert_eq!(rule.next_state(2, 8), 0);
+    }
+
+    #[test]
+    fn test_parse_generations_rule() {
+        let brians_brain = Rule::generations(&[2], &[], 3);
+        for notation in ["B2/S/C3", "b2sc3", "C3/B2/S", "/2/3"] {
+            assert_eq!(parse_rule(notation), Ok(brians_brain), "{}", notation);
+        }
+        let star_wars = Rule::generations(&[2], &[3, 4, 5], 4);
+        for notation in ["b2s345c4", "B2S345C4", "B2/S345C4", "b2/s345/c4"] {
+            assert_eq!(parse_rule(notation), Ok(star_wars), "{}", notation);
+        }
+        assert_eq!(parse_rule("b3s23c3"), parse_rule("B3/S23/C3"));
+        // With slashes the letter belongs to the counts: 2c, then 3.
+        assert!(parse_rule("B3/S2c3").unwrap().transitions.is_some());
+        assert_eq!(parse_rule("345/2/4"), Ok(Rule::generations(&[2], &[3, 4, 5], 4)));
+        assert_eq!(parse_rule("B3/S23/C2"), Ok(Rule::conway()));
+    }
+
+    #[test]
+    fn test_parse_generations_rule_errors() {
+        assert_eq!(parse_rule("B2/S/C"), Err(RuleParseError::InvalidStateCount(String::new())));
+        assert_eq!(parse_rule("B2/S/C1"), Err(RuleParseError::InvalidStateCount("1".to_string())));
+        assert_eq!(parse_rule("/2/256"), Err(RuleParseError::InvalidStateCount("256".to_string())));
+        assert_eq!(parse_rule("B2/S/C3c4"), Err(RuleParseError::DuplicateSection('C')));
+    }
+
+    #[test]
+    fn test_generations_rule_display() {
+        assert_eq!(parse_rule("/2/3").unwrap().to_string(), "B2/S/C3");
+        assert_eq!(parse_rule("345/2/4").unwrap().to_string(), "B2/S345/C4");
+    }
+
+    #[test]
+    fn test_parse_rule_spec_with_bounded_grid() {
+        let (rule, grid) = parse_rule_spec("B3/S23:T80,50").unwrap();
+        assert_eq!(rule, Rule::conway());
+        let grid = grid.unwrap();
+        assert_eq!(grid.topology, Topology::TORUS);
+        assert_eq!(grid.dimensions, Dimensions { width: 80, height: 50 });
+    }
+
+    #[test]
+    fn test_parse_rule_spec_without_bounded_grid() {
+        assert_eq!(parse_rule_spec("B36/S23"), Ok((Rule::highlife(), None)));
+    }
+
+    #[test]
+    fn test_parse_rule_spec_errors() {
+        assert_eq!(parse_rule_spec("B3/S23:"), Err(RuleParseError::InvalidTopology(TopologyParseError::Unknown(String::new()))));
+        assert_eq!(parse_rule_spec("B9/S23:T80,50"), Err(RuleParseError::CountOutOfRange('9')));
+        assert_eq!(parse_rule("B3/S23:T80,50"), Err(RuleParseError::UnexpectedCharacter(':')));
+    }
+
+    #[test]
+    fn test_parse_non_totalistic_rules() {
+        let just_friends = parse_rule("B2-a/S12").unwrap();
+        assert!(just_friends.transitions.is_some());
+        assert_eq!(just_friends.birth, 1 << 2);
+        assert_eq!(just_friends.survive, 1 << 1 | 1 << 2);
+        // Two neighbours on opposite sides, then two that touch.
+        let apart = neighbour_bit(0, -1) | neighbour_bit(0, 1);
+        let adjacent = neighbour_bit(0, -1) | neighbour_bit(1, -1);
+        assert_eq!(just_friends.next_state_for(0, apart), 1);
+        assert_eq!(just_friends.next_state_for(0, adjacent), 0);
+        assert_eq!(just_friends.next_state_for(1, adjacent), 1);
+        assert_eq!(just_friends.next_state_for(1, apart | adjacent), 0);
+
+        // As in Golly, 3a is a corner with both its edges and 3i a line.
+        let no_3a = parse_rule("B3/S23-a").unwrap();
+        let line = neighbour_bit(-1, -1) | neighbour_bit(0, -1) | neighbour_bit(1, -1);
+        let corner = neighbour_bit(-1, -1) | neighbour_bit(0, -1) | neighbour_bit(-1, 0);
+        assert_eq!(no_3a.next_state_for(1, line), 1);
+        assert_eq!(no_3a.next_state_for(1, corner), 0);
+    }
+
+    #[test]
+    fn test_totalistic_hensel_rule_is_plain_rule() {
+        assert_eq!(parse_rule("B3cekainyqjr/S2cekain3"), Ok(Rule::conway()));
+        assert_eq!(parse_rule("B3/S2-a2a3"), Ok(Rule::conway()));
+        let conway = Rule::conway();
+        for neighbourhood in 0..0x200u16 {
+            let count = (neighbourhood & !CENTRE).count_ones() as i32;
+            assert_eq!(conway.next_state_for(1, neighbourhood), conway.next_state(1, count));
+        }
+    }
+
+    #[test]
+    fn test_non_totalistic_rule_display_round_trips() {
+        for notation in ["B2-a/S12", "B3/S23-a4ei", "B3/S2-i34q", "B2ce3-k/S1e5y", "B2i34cz/S04ka5j"] {
+            let rule = parse_rule(notation).unwrap();
+            assert_eq!(rule.to_string(), notation);
+            assert_eq!(rule.to_string().parse::<Rule>(), Ok(rule));
+        }
+        assert_eq!(parse_rule("b2ic/s23-ea").unwrap().to_string(), "B2ci/S23-ea");
+        assert_eq!(parse_rule("B2-a/S12/C3").unwrap().to_string(), "B2-a/S12/C3");
+    }
+
+    #[test]
+    fn test_non_totalistic_generations_rule() {
+        let rule = parse_rule("B2-a/S12/C4").unwrap();
+        let adjacent = neighbour_bit(-1, 0) | neighbour_bit(-1, 1);
+        assert_eq!(rule.next_state_for(1, adjacent), 1);
+        assert_eq!(rule.next_state_for(1, 0), 2);
+        assert_eq!(rule.next_state_for(2, adjacent), 3);
+        assert_eq!(rule.next_state_for(3, adjacent), 0);
+    }
+
+    #[test]
+    fn test_parse_non_totalistic_rule_errors() {
+        let invalid = |group: &str| Err(RuleParseError::InvalidNeighbourhood(group.to_string()));
+        assert_eq!(parse_rule("B2y/S23"), invalid("2y"));
+        assert_eq!(parse_rule("B3/S1a"), invalid("1a"));
+        assert_eq!(parse_rule("B0c/S23"), invalid("0c"));
+        assert_eq!(parse_rule("B3/S23-"), invalid("3-"));
+        assert_eq!(parse_rule("B3/S2-aa"), invalid("2-aa"));
+        assert_eq!(parse_rule("B3/S4t-w"), invalid("4t-"));
+        assert_eq!(parse_rule("B3/S2A"), Err(RuleParseError::UnexpectedCharacter('A')));
+        assert_eq!(parse_rule("B-3/S23"), Err(RuleParseError::UnexpectedCharacter('-')));
+        assert_eq!(parse_rule("B9a/S23"), Err(RuleParseError::CountOutOfRange('9')));
+    }
+
+    const CONWAY_MAP: &str = "MAPARYXfhZofugWaH7oaIDogBZofuhogOiAaIDogIAAgAAWaH7oaIDogGiA6ICAAIAAaIDogIAAgACAAIAAAAAAAA";
+
+    /// A rule under which a cell copies its west neighbour, so everything
+    /// moves one cell east each generation.
+    fn east_shift() -> Rule {
+        let mut table = Transitions::default();
+        for index in (0..0x200u16).filter(|index| index & neighbour_bit(-1, 0) != 0) {
+            table.insert(index);
+        }
+        Rule::from_transitions(table, 2)
+    }
+
+    #[test]
+    fn test_parse_map_rule() {
+        assert_eq!(parse_rule(CONWAY_MAP), Ok(Rule::conway()));
+        assert_eq!(parse_rule(&format!("{}==", CONWAY_MAP)), Ok(Rule::conway()));
+        assert_eq!(parse_rule(&format!("{}/3", CONWAY_MAP)), Ok(Rule::generations(&[3], &[2, 3], 3)));
+        let shift = east_shift();
+        assert_eq!(shift.next_state_for(0, neighbour_bit(-1, 0)), 1);
+        assert_eq!(shift.next_state_for(1, neighbour_bit(1, 0)), 0);
+    }
+
+    #[test]
+    fn test_parse_larger_than_life_rule() {
+        let bosco = parse_rule("R5,C0,M1,S34..58,B34..45,NM").unwrap();
+        assert_eq!(bosco.radius(), 5);
+        assert_eq!(bosco.to_string(), "R5,C0,M1,S34..58,B34..45,NM");
+        assert_eq!(bosco.next_state(0, 34), 1);
+        assert_eq!(bosco.next_state(1, 33), 0);
+        assert_eq!(bosco.next_state(1, 58), 1);
+        assert!(bosco.to_map().is_none());
+        let decaying = parse_rule("R2,C4,M0,S2..5,B3..3,NN").unwrap();
+        assert_eq!(decaying.states, 4);
+        assert_eq!(decaying.next_state(1, 6), 2);
+        assert_eq!(parse_rule(&decaying.to_string()), Ok(decaying));
+        assert_eq!(parse_rule("R2,S1..30,B3..3"), Err(RuleParseError::InvalidRange("S1..30".to_string())));
+    }
+
+    #[test]
+    fn test_parse_named_rules() {
+        let wireworld = parse_rule("wireworld").unwrap();
+        assert_eq!(wireworld.states, 4);
+        assert_eq!(wireworld.to_string(), "WireWorld");
+        assert_eq!(parse_rule("Langtons-Loops").unwrap().neighbourhood, Neighbourhood::VonNeumann(1));
+        assert_eq!(parse_rule("Brian's Brain"), parse_rule("BriansBrain"));
+        assert_ne!(parse_rule("BriansBrain"), parse_rule("B2/S/C3"));
+        assert_eq!(wireworld.to_map(), None);
+    }
+
+    #[test]
+    fn test_radius_one_larger_than_life_is_conway() {
+        // Counting the cell itself moves survival up by one.
+        for rule in ["R1,C0,M0,S2..3,B3..3,NM", "R1,C0,M1,S3..4,B3..3,NM"] {
+            assert_eq!(parse_rule(rule).unwrap().to_map().as_deref(), Some(CONWAY_MAP), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn test_rules_export_as_map() {
+        assert_eq!(Rule::conway().to_map().as_deref(), Some(CONWAY_MAP));
+        for rule in ["B36/S23", "B2-a/S12", "B3/S2-i34q/C5", "B0/S8"] {
+            let rule = parse_rule(rule).unwrap();
+            assert_eq!(parse_rule(&rule.to_map().unwrap()), Ok(rule));
+        }
+        assert!(parse_rule("B2/S/C3").unwrap().to_map().unwrap().ends_with("/3"));
+    }
+
+    #[test]
+    fn test_non_isotropic_rule_displays_as_map() {
+        let shift = east_shift();
+        let text = shift.to_string();
+        assert!(text.starts_with("MAP"));
+        assert_eq!(parse_rule(&text), Ok(shift));
+    }
+
+    #[test]
+    fn test_parse_map_rule_errors() {
+        assert_eq!(parse_rule("MAPARYX"), Err(RuleParseError::InvalidMap("ARYX".to_string())));
+        let long = format!("{}A", CONWAY_MAP);
+        assert_eq!(parse_rule(&long), Err(RuleParseError::InvalidMap(long[3..].to_string())));
+        let generations = format!("{}/1", CONWAY_MAP);
+        assert_eq!(parse_rule(&generations), Err(RuleParseError::InvalidStateCount("1".to_string())));
+        assert!(matches!(parse_rule("MAPé"), Err(RuleParseError::InvalidMap(_))));
+    }
+
+    #[test]
+    fn test_parse_rule_with_neighbourhood() {
+        let rule = parse_rule("B2/S013V").unwrap();
+        assert_eq!(rule.neighbourhood, Neighbourhood::VonNeumann(1));
+        assert_eq!(rule, Rule::new(&[2], &[0, 1, 3]).with_neighbourhood(Neighbourhood::VonNeumann(1)));
+        assert_eq!(parse_rule("b2s34h").unwrap().neighbourhood, Neighbourhood::Hexagonal);
+        assert_eq!(parse_rule("B3/S23M"), Ok(Rule::conway()));
+        let von_neumann = Rule::generations(&[2], &[], 3).with_neighbourhood(Neighbourhood::VonNeumann(1));
+        assert_eq!(parse_rule("B2/SV/C3"), Ok(von_neumann));
+        let custom = parse_rule("B1/S@550").unwrap();
+        assert_eq!(custom.neighbourhood.offsets(), Neighbourhood::VonNeumann(1).offsets());
+    }
+
+    #[test]
+    fn test_neighbourhood_counts_only_its_cells() {
+        let rule = parse_rule("B2/S013V").unwrap();
+        let corners = neighbour_bit(-1, -1) | neighbour_bit(1, 1);
+        assert_eq!(rule.next_state_for(0, corners), 0);
+        assert_eq!(rule.next_state_for(0, corners | neighbour_bit(0, -1) | neighbour_bit(1, 0)), 1);
+        assert_eq!(rule.next_state_for(1, corners), 1);
+    }
+
+    #[test]
+    fn test_wide_neighbourhood_counts_use_commas() {
+        let rule = parse_rule("B3,10/S2,3,24M2").unwrap();
+        assert_eq!(rule.neighbourhood, Neighbourhood::Moore(2));
+        assert_eq!(rule.birth, 1 << 3 | 1 << 10);
+        assert_eq!(rule.survive, 1 << 2 | 1 << 3 | 1 << 24);
+        assert_eq!(rule.to_string(), "B3,10/S2,3,24M2");
+        assert_eq!(rule.next_state(0, 10), 1);
+        assert_eq!(rule.to_map(), None);
+        assert_eq!(parse_rule("B/S12V2").unwrap().to_string(), "B/S12V2");
+    }
+
+    #[test]
+    fn test_neighbourhood_display_round_trips() {
+        for notation in ["B2/S013V", "B2/S34H", "B1/S@550", "B3/S23V3", "B2/S013V/C4"] {
+            let rule = parse_rule(notation).unwrap();
+            assert_eq!(rule.to_string(), notation);
+        }
+        let hexagonal = parse_rule("B2/S34H").unwrap();
+        assert_eq!(parse_rule(&hexagonal.to_map().unwrap()).unwrap().transition_table(), hexagonal.transition_table());
+    }
+
+    #[test]
+    fn test_parse_rule_rejects_unreachable_counts() {
+        assert_eq!(parse_rule("B5/S013V"), Err(RuleParseError::CountOutOfRange('5')));
+        assert_eq!(parse_rule("B2/S7H"), Err(RuleParseError::CountOutOfRange('7')));
+        assert_eq!(parse_rule("B3/S2,25M2"), Err(RuleParseError::InvalidCount("25".to_string())));
+        assert_eq!(parse_rule("B3/S2,xV2"), Err(RuleParseError::UnexpectedCharacter('x')));
+        assert_eq!(parse_rule("B3/S23M4"), Err(RuleParseError::InvalidNeighbourhoodShape("M4".to_string())));
+        assert_eq!(parse_rule("B3/S23VH"), Err(RuleParseError::DuplicateSection('H')));
+        assert_eq!(parse_rule("B2a/S12V"), Err(RuleParseError::UnexpectedCharacter('a')));
     }
 
     #[test]
-    fn test_parse_rule_invalid_defaults_to_conway() {
-        let rule = parse_rule("b45s67");
-        assert_eq!(rule(true, 2), true);
-        assert_eq!(rule(false, 3), true);
+    fn test_rule_kind() {
+        assert_eq!(parse_rule("B36/S23/C3").unwrap().kind(), RuleKind::Totalistic);
+        assert_eq!(parse_rule("B3/S23V").unwrap().kind(), RuleKind::Totalistic);
+        assert_eq!(parse_rule("B2-a/S12").unwrap().kind(), RuleKind::NonTotalistic);
+        assert_eq!(parse_rule("R5,C0,M1,S34..58,B34..45,NM").unwrap().kind(), RuleKind::LargerThanLife);
+        assert_eq!(parse_rule("WireWorld").unwrap().kind(), RuleKind::Named);
     }
 }
@@ -0,0 +1,348 @@
+use std::fmt::Write;
+
+use crate::pattern::{Pattern, PatternError};
+use crate::resources::parse_rule_spec;
+
+/// Column at which `write` wraps the body.
+pub const LINE_WIDTH: usize = 70;
+
+/// Parses a Run Length Encoded pattern as written by Golly and LifeWiki.
+///
+/// `#N`, `#O` and `#C` lines give the name, author and comments. The
+/// `x = .., y = ..` header may carry a `rule = ..`, with a Golly bounded grid
+/// suffix such as `:T80,50`. In the body `b` or `.` is a dead cell, `o` a
+/// live one and `A` to `X`, optionally prefixed by `p` to `y`, the states of
+/// multi-state rules.
+pub fn parse(text: &str) -> Result<Pattern, PatternError> {
+    let mut pattern = Pattern::default();
+    let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+
+    let header = loop {
+        let line = lines.next().ok_or(PatternError::MissingHeader)?;
+        match line.strip_prefix('#') {
+            Some(comment) => read_comment(&mut pattern, comment),
+            None => break line,
+        }
+    };
+    read_header(&mut pattern, header)?;
+
+    let mut body = String::new();
+    for line in lines {
+        match line.strip_prefix('#') {
+            Some(comment) => read_comment(&mut pattern, comment),
+            None => body.push_str(line),
+        }
+    }
+    read_body(&mut pattern, &body)?;
+    Ok(pattern)
+}
+
+/// Parses an RLE body without a header, boxing whatever cells it holds.
+pub fn parse_body(body: &str) -> Result<Pattern, PatternError> {
+    let mut pattern = Pattern::default();
+    read_body(&mut pattern, body)?;
+    Ok(Pattern::from_grid(&pattern.cells))
+}
+
+fn read_comment(pattern: &mut Pattern, comment: &str) {
+    let mut chars = comment.chars();
+    let kind = chars.next();
+    let text = chars.as_str().trim().to_string();
+    match kind {
+        Some('N') => pattern.name = Some(text),
+        Some('O') => pattern.author = Some(text),
+        Some('C' | 'c') => pattern.comments.push(text),
+        _ => {}
+    }
+}
+
+fn read_header(pattern: &mut Pattern, header: &str) -> Result<(), PatternError> {
+    let invalid = || PatternError::InvalidHeader(header.to_string());
+
+    // A bounded grid suffix such as `:T80,50` contains a comma, so the rule
+    // is taken to the end of the line.
+    let (sizes, rule) = match header.find("rule") {
+        Some(index) => (&header[..index], Some(&header[index + "rule".len()..])),
+        None => (header, None),
+    };
+    let (mut width, mut height) = (None, None);
+    for field in sizes.split(',').map(str::trim).filter(|field| !field.is_empty()) {
+        let (key, value) = field.split_once('=').ok_or_else(invalid)?;
+        let value = value.trim().parse::<i32>().map_err(|_| invalid())?;
+        match key.trim() {
+            "x" => width = Some(value),
+            "y" => height = Some(value),
+            _ => return Err(invalid()),
+        }
+    }
+    pattern.width = width.ok_or_else(invalid)?;
+    pattern.height = height.ok_or_else(invalid)?;
+
+    if let Some(rule) = rule {
+        let rule = rule.trim_start().strip_prefix('=').ok_or_else(invalid)?;
+        let (rule, bounded_grid) = parse_rule_spec(rule.trim()).map_err(PatternError::InvalidRule)?;
+        pattern.rule = Some(rule);
+        pattern.bounded_grid = bounded_grid;
+    }
+    Ok(())
+}
+
+fn read_body(pattern: &mut Pattern, body: &str) -> Result<(), PatternError> {
+    let (mut x, mut y) = (0, 0);
+    let mut count: Option<i32> = None;
+    let mut prefix: Option<char> = None;
+
+    for c in body.chars() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if let Some(digit) = c.to_digit(10) {
+            count = Some(count.unwrap_or(0) * 10 + digit as i32);
+            continue;
+        }
+        let run = count.take().unwrap_or(1);
+        let state = match (prefix.take(), c) {
+            (None, '!') => break,
+            (None, '$') => {
+                x = 0;
+                y += run;
+                continue;
+            }
+            (None, 'p'..='y') => {
+                prefix = Some(c);
+                count = Some(run);
+                continue;
+            }
+            (None, 'b' | '.') => 0,
+            (None, 'o') => 1,
+            (None, 'A'..='X') => letter_state(c),
+            (Some(prefix), 'A'..='X') => 24 * (prefix as i32 - 'p' as i32 + 1) + letter_state(c),
+            _ => return Err(PatternError::UnexpectedCharacter(c)),
+        };
+        if state != 0 {
+            for i in 0..run {
+                pattern.cells.insert((x + i, y), state);
+            }
+        }
+        x += run;
+    }
+    Ok(())
+}
+
+fn letter_state(c: char) -> i32 {
+    c as i32 - 'A' as i32 + 1
+}
+
+/// Writes a pattern as RLE that `parse` and Golly read back: its name,
+/// author and comments, a header with its box and rule, and the body
+/// wrapped at `LINE_WIDTH` columns. The box grows to hold every cell.
+pub fn write(pattern: &Pattern) -> String {
+    let pattern = &pattern.boxed();
+    let mut out = String::new();
+    if let Some(name) = &pattern.name {
+        writeln!(out, "#N {}", name).unwrap();
+    }
+    if let Some(author) = &pattern.author {
+        writeln!(out, "#O {}", author).unwrap();
+    }
+    for comment in &pattern.comments {
+        writeln!(out, "#C {}", comment).unwrap();
+    }
+
+    write!(out, "x = {}, y = {}", pattern.width.max(0), pattern.height.max(0)).unwrap();
+    if let Some(rule) = &pattern.rule {
+        write!(out, ", rule = {}", rule).unwrap();
+        if let Some(grid) = &pattern.bounded_grid {
+            write!(out, ":{}", grid).unwrap();
+        }
+    }
+    out.push('\n');
+
+    let multi_state = pattern.rule.is_some_and(|rule| rule.states > 2) || pattern.cells.values().any(|&state| state > 1);
+    let mut rows: Vec<Vec<(i32, i32)>> = vec![Vec::new(); pattern.height.max(0) as usize];
+    for (&(x, y), &state) in &pattern.cells {
+        rows[y as usize].push((x, state));
+    }
+
+    let mut line = String::new();
+    let mut emit = |run: i32, symbol: String| {
+        let token = if run > 1 { format!("{}{}", run, symbol) } else { symbol };
+        if line.len() + token.len() > LINE_WIDTH {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        line.push_str(&token);
+    };
+    let mut last_row = None;
+    for (y, row) in rows.iter().enumerate().filter(|(_, row)| !row.is_empty()) {
+        if let Some(last_row) = last_row {
+            emit((y - last_row) as i32, "$".to_string());
+        }
+        last_row = Some(y);
+
+        let mut x = 0;
+        let mut runs: Vec<(i32, i32)> = Vec::new();
+        for &(cell_x, state) in row {
+            if cell_x > x {
+                runs.push((cell_x - x, 0));
+            }
+            match runs.last_mut() {
+                Some((run, last)) if *last == state => *run += 1,
+                _ => runs.push((1, state)),
+            }
+            x = cell_x + 1;
+        }
+        for (run, state) in runs {
+            emit(run, state_symbol(state, multi_state));
+        }
+    }
+    emit(1, "!".to_string());
+    out.push_str(&line);
+    out.push('\n');
+    out
+}
+
+fn state_symbol(state: i32, multi_state: bool) -> String {
+    match (state, multi_state) {
+        (0, false) => "b".to_string(),
+        (_, false) => "o".to_string(),
+        (0, true) => ".".to_string(),
+        (1..=24, true) => letter(state).to_string(),
+        (_, true) => format!("{}{}", (b'p' + ((state - 25) / 24) as u8) as char, letter((state - 25) % 24 + 1)),
+    }
+}
+
+fn letter(state: i32) -> char {
+    (b'A' + (state - 1) as u8) as char
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::{parse_rule, Rule};
+    use crate::topology::BoundedGrid;
+
+    const GLIDER: &str = "#N Glider
+#O Richard K. Guy
+#C The smallest, most common, and first discovered spaceship.
+#C www.conwaylife.com/wiki/index.php?title=Glider
+x = 3, y = 3, rule = B3/S23
+bob$2bo$3o!
+";
+
+    #[test]
+    fn test_parse_glider() {
+        let pattern = parse(GLIDER).unwrap();
+        assert_eq!(pattern.name.as_deref(), Some("Glider"));
+        assert_eq!(pattern.author.as_deref(), Some("Richard K. Guy"));
+        assert_eq!(pattern.comments.len(), 2);
+        assert_eq!(pattern.rule, Some(Rule::conway()));
+        assert_eq!((pattern.width, pattern.height), (3, 3));
+        let cells: Vec<(i32, i32)> = pattern.cells.keys().copied().collect();
+        assert_eq!(cells, vec![(0, 2), (1, 0), (1, 2), (2, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_parse_runs_across_lines_and_blank_rows() {
+        let pattern = parse("x = 12, y = 4\n3o5b\n4o$\n\n2$12o!").unwrap();
+        assert_eq!(pattern.rule, None);
+        assert_eq!(pattern.cells.len(), 19);
+        assert_eq!(pattern.cells.get(&(8, 0)), Some(&1));
+        assert_eq!(pattern.cells.get(&(11, 0)), Some(&1));
+        assert_eq!(pattern.cells.get(&(11, 3)), Some(&1));
+        assert_eq!(pattern.cells.get(&(0, 1)), None);
+    }
+
+    #[test]
+    fn test_parse_multi_state_letters() {
+        let pattern = parse("x = 5, y = 2, rule = /2/3\n.A2B$pApX!").unwrap();
+        assert_eq!(pattern.rule, Some(parse_rule("B2/S/C3").unwrap()));
+        assert_eq!(pattern.cells.get(&(0, 0)), None);
+        assert_eq!(pattern.cells.get(&(1, 0)), Some(&1));
+        assert_eq!(pattern.cells.get(&(3, 0)), Some(&2));
+        assert_eq!(pattern.cells.get(&(0, 1)), Some(&25));
+        assert_eq!(pattern.cells.get(&(1, 1)), Some(&48));
+    }
+
+    #[test]
+    fn test_parse_rule_with_bounded_grid() {
+        let pattern = parse("x = 3, y = 1, rule = B3/S23:T80,50\n3o!").unwrap();
+        assert_eq!(pattern.rule, Some(Rule::conway()));
+        assert_eq!(pattern.bounded_grid, Some("T80,50".parse::<BoundedGrid>().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_body() {
+        let pattern = parse_body("2$3b2o$3b2o!").unwrap();
+        assert_eq!((pattern.width, pattern.height), (2, 2));
+        assert_eq!(pattern.cells.keys().copied().collect::<Vec<_>>(), vec![(0, 0), (0, 1), (1, 0), (1, 1)]);
+    }
+
+    #[test]
+    fn test_write_glider() {
+        let pattern = parse(GLIDER).unwrap();
+        let expected = "#N Glider
+#O Richard K. Guy
+#C The smallest, most common, and first discovered spaceship.
+#C www.conwaylife.com/wiki/index.php?title=Glider
+x = 3, y = 3, rule = B3/S23
+bo$2bo$3o!
+";
+        assert_eq!(write(&pattern), expected);
+    }
+
+    #[test]
+    fn test_write_round_trips() {
+        let sources = [
+            "x = 12, y = 6, rule = B36/S23:T80,50\n3o5b4o$$4bo$12o$$o!",
+            "x = 5, y = 2, rule = B2/S/C3\n.A2B$pApX!",
+            "x = 3, y = 1, rule = R5,C0,M1,S34..58,B34..45,NM:T100,100\n3o!",
+            "x = 6, y = 3, rule = WireWorld\n.4C$C4.C$.BA2C!",
+            "x = 0, y = 0\n!",
+        ];
+        for source in sources {
+            let pattern = parse(source).unwrap();
+            assert_eq!(parse(&write(&pattern)).unwrap(), pattern, "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_write_grows_the_box_to_the_body() {
+        // The body is wider and taller than its header says.
+        let pattern = parse("x = 2, y = 1, rule = B3/S23\n3o$o!").unwrap();
+        assert_eq!(write(&pattern), "x = 3, y = 2, rule = B3/S23\n3o$o!\n");
+
+        let mut pattern = Pattern { width: 1, height: 1, ..Pattern::default() };
+        pattern.cells.insert((-1, -2), 1);
+        pattern.cells.insert((0, 0), 1);
+        assert_eq!(write(&pattern), "x = 2, y = 3\no2$bo!\n");
+    }
+
+    #[test]
+    fn test_write_wraps_long_rows() {
+        let mut pattern = Pattern { width: 401, height: 3, ..Pattern::default() };
+        for x in (0..400).step_by(3) {
+            pattern.cells.insert((x, 0), 1);
+            pattern.cells.insert((x + 1, 2), 1);
+        }
+        let text = write(&pattern);
+        assert!(text.lines().all(|line| line.len() <= LINE_WIDTH));
+        assert!(text.lines().count() > 5);
+        assert_eq!(parse(&text).unwrap(), pattern);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse("#C only comments\n"), Err(PatternError::MissingHeader));
+        assert_eq!(
+            parse("x = 3\n3o!"),
+            Err(PatternError::InvalidHeader("x = 3".to_string()))
+        );
+        assert!(matches!(
+            parse("x = 3, y = 1, rule = B9\n3o!"),
+            Err(PatternError::InvalidRule(_))
+        ));
+        assert_eq!(parse("x = 3, y = 1\n3z!"), Err(PatternError::UnexpectedCharacter('z')));
+    }
+}
@@ -1,10 +1,14 @@
-use rand::{thread_rng, Rng};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
 pub type Grid = BTreeMap<(i32, i32), i32>;
 
 pub enum Seeder {
-    Random,
+    Random(Soup),
     Glider,
     CenterOne,
     CenterFive,
@@ -14,7 +18,7 @@ impl Seeder {
     pub fn seed(&self, width: i32, height: i32) -> Grid {
         match *self {
             Seeder::Glider => grid_glider(width, height),
-            Seeder::Random => grid_random(width, height),
+            Seeder::Random(ref soup) => grid_random(soup, width, height),
             Seeder::CenterOne => grid_center_one(width, height),
             Seeder::CenterFive => grid_center_five(width, height),
         }
@@ -22,7 +26,7 @@ impl Seeder {
 
     pub fn new(index: u32) -> Self {
         match index {
-            0 => Seeder::Random,
+            0 => Seeder::Random(Soup::default()),
             1 => Seeder::Glider,
             2 => Seeder::CenterOne,
             3 => Seeder::CenterFive,
@@ -45,12 +49,209 @@ fn grid_glider(width: i32, height: i32) -> Grid {
     grid
 }
 
-fn grid_random(width: i32, height: i32) -> Grid {
+/// A random soup. The same `seed` gives the same board on every run and
+/// platform. Cells are alive with probability `density`, inside a centred
+/// box grown from `size` by the symmetry, or across the whole board.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Soup {
+    pub seed: u64,
+    pub density: f64,
+    pub size: Option<i32>,
+    pub symmetry: Symmetry,
+}
+
+impl Default for Soup {
+    fn default() -> Self {
+        Soup {
+            seed: 0,
+            density: 0.5,
+            size: None,
+            symmetry: Symmetry::default(),
+        }
+    }
+}
+
+impl Soup {
+    pub fn new(seed: u64) -> Self {
+        Soup { seed, ..Soup::default() }
+    }
+
+    pub fn with_density(mut self, density: f64) -> Result<Self, SoupError> {
+        if !(0.0..=1.0).contains(&density) {
+            return Err(SoupError::InvalidDensity(density.to_string()));
+        }
+        self.density = density;
+        Ok(self)
+    }
+
+    pub fn with_size(mut self, size: i32) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    pub fn with_symmetry(mut self, symmetry: Symmetry) -> Self {
+        self.symmetry = symmetry;
+        self
+    }
+}
+
+/// The symmetry groups of apgsearch's soups.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Group {
+    C1,
+    C2,
+    C4,
+    D2Plus,
+    D2Diagonal,
+    D4Plus,
+    D4Diagonal,
+    D8,
+}
+
+impl Group {
+    /// Cells share an orbit under rotations that need a square box.
+    fn needs_square(&self) -> bool {
+        matches!(self, Group::C4 | Group::D2Diagonal | Group::D4Diagonal | Group::D8)
+    }
+}
+
+/// A soup symmetry named as in apgsearch, e.g. `C1`, `C2_4`, `D4_+2` or
+/// `D8_1`. The suffix places the centre: `1` on a cell, `4` on a cell
+/// corner and `2` on the middle of a horizontal edge.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Symmetry {
+    group: Group,
+    odd_width: bool,
+    odd_height: bool,
+}
+
+impl Default for Symmetry {
+    fn default() -> Self {
+        Symmetry {
+            group: Group::C1,
+            odd_width: false,
+            odd_height: false,
+        }
+    }
+}
+
+impl Symmetry {
+    pub const NAMES: [&'static str; 16] = [
+        "C1", "C2_1", "C2_2", "C2_4", "C4_1", "C4_4", "D2_+1", "D2_+2", "D2_x", "D4_+1", "D4_+2", "D4_+4", "D4_x1",
+        "D4_x4", "D8_1", "D8_4",
+    ];
+
+    /// The box a soup of `size` fills once its cells are mirrored.
+    fn dimensions(&self, size: i32) -> (i32, i32) {
+        let double = |odd| if odd { 2 * size - 1 } else { 2 * size };
+        match self.group {
+            Group::C1 | Group::D2Diagonal => (size, size),
+            Group::D2Plus => (size, double(self.odd_height)),
+            _ => (double(self.odd_width), double(self.odd_height)),
+        }
+    }
+
+    /// Every cell of a `width` x `height` box that `(x, y)` is mapped to.
+    fn images(&self, x: i32, y: i32, width: i32, height: i32) -> Vec<(i32, i32)> {
+        let (fx, fy) = (width - 1 - x, height - 1 - y);
+        match self.group {
+            Group::C1 => vec![(x, y)],
+            Group::C2 => vec![(x, y), (fx, fy)],
+            Group::C4 => vec![(x, y), (fy, x), (fx, fy), (y, fx)],
+            Group::D2Plus => vec![(x, y), (x, fy)],
+            Group::D2Diagonal => vec![(x, y), (y, x)],
+            Group::D4Plus => vec![(x, y), (fx, y), (x, fy), (fx, fy)],
+            Group::D4Diagonal => vec![(x, y), (y, x), (fy, fx), (fx, fy)],
+            Group::D8 => vec![(x, y), (fy, x), (fx, fy), (y, fx), (fx, y), (x, fy), (y, x), (fy, fx)],
+        }
+    }
+}
+
+impl FromStr for Symmetry {
+    type Err = SoupError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (group, odd_width, odd_height) = match s.trim() {
+            "C1" => (Group::C1, false, false),
+            "C2_1" => (Group::C2, true, true),
+            "C2_2" => (Group::C2, false, true),
+            "C2_4" => (Group::C2, false, false),
+            "C4_1" => (Group::C4, true, true),
+            "C4_4" => (Group::C4, false, false),
+            "D2_+1" => (Group::D2Plus, false, true),
+            "D2_+2" => (Group::D2Plus, false, false),
+            "D2_x" => (Group::D2Diagonal, false, false),
+            "D4_+1" => (Group::D4Plus, true, true),
+            "D4_+2" => (Group::D4Plus, false, true),
+            "D4_+4" => (Group::D4Plus, false, false),
+            "D4_x1" => (Group::D4Diagonal, true, true),
+            "D4_x4" => (Group::D4Diagonal, false, false),
+            "D8_1" => (Group::D8, true, true),
+            "D8_4" => (Group::D8, false, false),
+            _ => return Err(SoupError::InvalidSymmetry(s.to_string())),
+        };
+        Ok(Symmetry {
+            group,
+            odd_width,
+            odd_height,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SoupError {
+    InvalidDensity(String),
+    InvalidSymmetry(String),
+}
+
+impl fmt::Display for SoupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SoupError::InvalidDensity(density) => write!(f, "invalid density '{}', expected 0 to 1", density),
+            SoupError::InvalidSymmetry(name) => {
+                write!(f, "unknown symmetry '{}', expected one of {}", name, Symmetry::NAMES.join(", "))
+            }
+        }
+    }
+}
+
+impl Error for SoupError {}
+
+fn grid_random(soup: &Soup, width: i32, height: i32) -> Grid {
+    let symmetry = soup.symmetry;
+    let (mut box_width, mut box_height) = match soup.size {
+        Some(size) => symmetry.dimensions(size.max(1)),
+        None => (width, height),
+    };
+    box_width = box_width.min(width);
+    box_height = box_height.min(height);
+    if symmetry.group.needs_square() {
+        box_width = box_width.min(box_height);
+        box_height = box_width;
+    }
+
+    // Cells are drawn column by column, so the first cell of each orbit in
+    // that order is the one its state is drawn for. Unlike `StdRng`,
+    // ChaCha8's output is the same in every version of the crate.
+    let mut rng = ChaCha8Rng::seed_from_u64(soup.seed);
+    let mut soup_cells = BTreeMap::new();
+    for x in 0..box_width {
+        for y in 0..box_height {
+            let first = symmetry.images(x, y, box_width, box_height).into_iter().min().unwrap_or((x, y));
+            let state = match soup_cells.get(&first) {
+                Some(&state) => state,
+                None => rng.gen_bool(soup.density) as i32,
+            };
+            soup_cells.insert((x, y), state);
+        }
+    }
+
+    let (left, top) = ((width - box_width) / 2, (height - box_height) / 2);
     let mut grid = Grid::new();
-    let mut rng = thread_rng();
-    for row in 0..width {
-        for col in 0..height {
-            grid.insert((row, col), rng.gen_range(0..2));
+    for x in 0..width {
+        for y in 0..height {
+            let state = soup_cells.get(&(x - left, y - top)).copied().unwrap_or(0);
+            grid.insert((x, y), state);
         }
     }
     grid
@@ -90,34 +291,22 @@ mod tests {
 
     #[test]
     fn test_seeder_new_random() {
-        match Seeder::new(0) {
-            Seeder::Random => assert!(true),
-            _ => panic!("Expected Seeder::Random"),
-        }
+        assert!(matches!(Seeder::new(0), Seeder::Random(_)));
     }
 
     #[test]
     fn test_seeder_new_glider() {
-        match Seeder::new(1) {
-            Seeder::Glider => assert!(true),
-            _ => panic!("Expected Seeder::Glider"),
-        }
+        assert!(matches!(Seeder::new(1), Seeder::Glider));
     }
 
     #[test]
     fn test_seeder_new_center_one() {
-        match Seeder::new(2) {
-            Seeder::CenterOne => assert!(true),
-            _ => panic!("Expected Seeder::CenterOne"),
-        }
+        assert!(matches!(Seeder::new(2), Seeder::CenterOne));
     }
 
     #[test]
     fn test_seeder_new_center_five() {
-        match Seeder::new(3) {
-            Seeder::CenterFive => assert!(true),
-            _ => panic!("Expected Seeder::CenterFive"),
-        }
+        assert!(matches!(Seeder::new(3), Seeder::CenterFive));
     }
 
     #[test]
@@ -146,13 +335,13 @@ mod tests {
 
     #[test]
     fn test_grid_random_dimensions() {
-        let grid = grid_random(5, 7);
+        let grid = grid_random(&Soup::default(), 5, 7);
         assert_eq!(grid.len(), 35);
     }
 
     #[test]
     fn test_grid_random_values() {
-        let grid = grid_random(10, 10);
+        let grid = grid_random(&Soup::default(), 10, 10);
         for (_, &value) in grid.iter() {
             assert!(value == 0 || value == 1);
         }
@@ -205,4 +394,89 @@ mod tests {
         let grid = seeder.seed(10, 10);
         assert_eq!(grid.get(&(5, 5)), Some(&1));
     }
+
+    fn live(grid: &Grid) -> Vec<(i32, i32)> {
+        grid.iter().filter(|(_, &state)| state == 1).map(|(&pos, _)| pos).collect()
+    }
+
+    #[test]
+    fn test_soup_is_reproducible() {
+        let soup = Soup::new(42).with_size(16);
+        assert_eq!(Seeder::Random(soup).seed(40, 40), Seeder::Random(soup).seed(40, 40));
+        assert_ne!(live(&grid_random(&soup, 40, 40)), live(&grid_random(&Soup::new(43).with_size(16), 40, 40)));
+    }
+
+    #[test]
+    fn test_soup_is_the_same_on_every_platform() {
+        let grid = grid_random(&Soup::new(1).with_size(8), 8, 8);
+        let rows: Vec<String> = (0..8)
+            .map(|y| (0..8).map(|x| if grid[&(x, y)] == 1 { 'o' } else { '.' }).collect())
+            .collect();
+        let expected = [
+            "o..oo.o.", "o..o..o.", ".o......", "o.oo.o..", "ooooooo.", ".ooo...o", "ooo.oo..", "ooo.....",
+        ];
+        assert_eq!(rows, expected);
+    }
+
+    #[test]
+    fn test_soup_density_and_size() {
+        let full = grid_random(&Soup::new(7).with_density(1.0).unwrap().with_size(16), 40, 30);
+        assert_eq!(full.len(), 1200);
+        let cells = live(&full);
+        assert_eq!(cells.len(), 256);
+        assert_eq!(cells.first(), Some(&(12, 7)));
+        assert_eq!(cells.last(), Some(&(27, 22)));
+
+        let empty = grid_random(&Soup::new(7).with_density(0.0).unwrap(), 40, 30);
+        assert!(live(&empty).is_empty());
+        let sparse = grid_random(&Soup::new(7).with_density(0.1).unwrap(), 100, 100);
+        assert!((700..1300).contains(&live(&sparse).len()));
+
+        assert_eq!(Soup::new(7).with_density(1.5), Err(SoupError::InvalidDensity("1.5".to_string())));
+    }
+
+    #[test]
+    fn test_soup_symmetries() {
+        let boxes = [
+            ("C1", 16, 16),
+            ("C2_1", 31, 31),
+            ("C2_2", 32, 31),
+            ("C2_4", 32, 32),
+            ("C4_1", 31, 31),
+            ("C4_4", 32, 32),
+            ("D2_+1", 16, 31),
+            ("D2_+2", 16, 32),
+            ("D2_x", 16, 16),
+            ("D4_+1", 31, 31),
+            ("D4_+2", 32, 31),
+            ("D4_+4", 32, 32),
+            ("D4_x1", 31, 31),
+            ("D4_x4", 32, 32),
+            ("D8_1", 31, 31),
+            ("D8_4", 32, 32),
+        ];
+        assert_eq!(boxes.len(), Symmetry::NAMES.len());
+        for (name, width, height) in boxes {
+            let symmetry: Symmetry = name.parse().unwrap();
+            assert_eq!(symmetry.dimensions(16), (width, height), "{}", name);
+
+            let soup = Soup::new(3).with_density(0.4).unwrap().with_size(16).with_symmetry(symmetry);
+            let grid = grid_random(&soup, width, height);
+            for (&(x, y), &state) in &grid {
+                for image in symmetry.images(x, y, width, height) {
+                    assert_eq!(grid[&image], state, "{} at {:?}", name, (x, y));
+                }
+            }
+            assert!(!live(&grid).is_empty(), "{}", name);
+        }
+        assert_eq!("D8_2".parse::<Symmetry>(), Err(SoupError::InvalidSymmetry("D8_2".to_string())));
+    }
+
+    #[test]
+    fn test_square_symmetries_fit_the_board() {
+        let soup = Soup::new(5).with_density(1.0).unwrap().with_symmetry("C4_4".parse().unwrap());
+        let cells = live(&grid_random(&soup, 30, 20));
+        assert_eq!(cells.len(), 400);
+        assert_eq!(cells.first(), Some(&(5, 0)));
+    }
 }
@@ -0,0 +1,342 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::ltl::{Ltl, SummedArea};
+use crate::named_rules::NamedRule;
+use crate::resources::{neighbour_bit, Rule};
+use crate::seeder::Grid;
+use crate::universe::{Simulation, OFFSETS};
+
+/// Side of the square tiles a Larger than Life generation is counted in.
+const TILE: i64 = 64;
+
+/// An unbounded universe that only stores cells which are not dead, keyed by
+/// their position on the infinite plane. Only cells near live ones are
+/// evaluated, so B0 rules, which fill the empty plane, are not supported.
+pub struct SparseUniverse {
+    rule: Rule,
+    cells: HashMap<(i64, i64), u8>,
+    generation: u64,
+    changed: Vec<(i64, i64)>,
+}
+
+impl SparseUniverse {
+    pub fn new(rule: Rule) -> Self {
+        SparseUniverse {
+            rule,
+            cells: HashMap::new(),
+            generation: 0,
+            changed: Vec::new(),
+        }
+    }
+
+    pub fn from_grid(grid: &Grid, rule: Rule) -> Self {
+        let mut universe = SparseUniverse::new(rule);
+        for (&(x, y), &state) in grid {
+            universe.set(x as i64, y as i64, state as u8);
+        }
+        universe
+    }
+
+    /// Smallest `(min_x, min_y, max_x, max_y)` box holding every cell that is
+    /// not dead, or `None` for an empty universe.
+    pub fn bounding_box(&self) -> Option<(i64, i64, i64, i64)> {
+        self.cells.keys().fold(None, |bounds, &(x, y)| match bounds {
+            None => Some((x, y, x, y)),
+            Some((min_x, min_y, max_x, max_y)) => Some((min_x.min(x), min_y.min(y), max_x.max(x), max_y.max(y))),
+        })
+    }
+}
+
+impl SparseUniverse {
+    fn next_cells(&self) -> HashMap<(i64, i64), u8> {
+        // Each cell's 3x3 neighbourhood index, or its live count for
+        // neighbourhoods reaching further.
+        let wide = self.rule.neighbourhood.radius() > 1;
+        let offsets = if wide { self.rule.neighbourhood.offsets() } else { OFFSETS.to_vec() };
+        let mut neighbourhoods: HashMap<(i64, i64), u16> = HashMap::new();
+        for (&(x, y), _) in self.cells.iter().filter(|(_, &state)| state == 1) {
+            for &(dx, dy) in &offsets {
+                let neighbourhood = neighbourhoods.entry((x - dx as i64, y - dy as i64)).or_insert(0);
+                *neighbourhood = if wide { *neighbourhood + 1 } else { *neighbourhood | neighbour_bit(dx, dy) };
+            }
+        }
+        let rule = self.rule;
+        let next_state = |state, neighbourhood: u16| match wide {
+            true => rule.next_state(state, neighbourhood as i32),
+            false => rule.next_state_for(state, neighbourhood),
+        };
+
+        let mut next = HashMap::with_capacity(self.cells.len());
+        for (&pos, &state) in &self.cells {
+            let new_state = next_state(state, neighbourhoods.get(&pos).copied().unwrap_or(0));
+            if new_state != 0 {
+                next.insert(pos, new_state);
+            }
+        }
+        for (&pos, &neighbourhood) in &neighbourhoods {
+            if self.cells.contains_key(&pos) {
+                continue;
+            }
+            let new_state = next_state(0, neighbourhood);
+            if new_state != 0 {
+                next.insert(pos, new_state);
+            }
+        }
+        next
+    }
+
+    /// The next generation under a Larger than Life rule. The plane is cut
+    /// into tiles, and only the tiles holding cells and those around them
+    /// are counted, each with a summed-area table of its own, so clusters
+    /// far apart cost no more than each on its own.
+    fn next_larger(&self, ltl: &Ltl) -> HashMap<(i64, i64), u8> {
+        let occupied: HashSet<(i64, i64)> =
+            self.cells.keys().map(|&(x, y)| (x.div_euclid(TILE), y.div_euclid(TILE))).collect();
+        // The radius is less than a tile, so only cells in a tile next to
+        // an occupied one can change.
+        let tiles: HashSet<(i64, i64)> = occupied
+            .iter()
+            .flat_map(|&(tx, ty)| (-1..=1).flat_map(move |dy| (-1..=1).map(move |dx| (tx + dx, ty + dy))))
+            .collect();
+
+        let radius = ltl.radius as i64;
+        let side = (TILE + 2 * radius) as usize;
+        let mut next = HashMap::with_capacity(self.cells.len());
+        for (tx, ty) in tiles {
+            let (left, top) = (tx * TILE, ty * TILE);
+            let sums = SummedArea::new(ltl, (left - radius, top - radius), side, side, |x, y| self.get(x, y) == 1);
+            for y in top..top + TILE {
+                for x in left..left + TILE {
+                    let new_state = self.rule.next_state(self.get(x, y), sums.count(x, y));
+                    if new_state != 0 {
+                        next.insert((x, y), new_state);
+                    }
+                }
+            }
+        }
+        next
+    }
+
+    /// The next generation under a named rule, trying every cell that is
+    /// not dead and every cell next to one.
+    fn next_named(&self, named: &NamedRule) -> HashMap<(i64, i64), u8> {
+        let offsets = named.neighbourhood.offsets();
+        let mut candidates: Vec<(i64, i64)> = self
+            .cells
+            .keys()
+            .flat_map(|&(x, y)| {
+                std::iter::once((x, y)).chain(offsets.iter().map(move |&(dx, dy)| (x - dx as i64, y - dy as i64)))
+            })
+            .collect();
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let mut next = HashMap::with_capacity(self.cells.len());
+        let mut neighbours = Vec::with_capacity(offsets.len());
+        for (x, y) in candidates {
+            neighbours.clear();
+            neighbours.extend(offsets.iter().map(|&(dx, dy)| self.get(x + dx as i64, y + dy as i64)));
+            let new_state = (named.next_state)(self.get(x, y), &neighbours);
+            if new_state != 0 {
+                next.insert((x, y), new_state);
+            }
+        }
+        next
+    }
+}
+
+impl Simulation for SparseUniverse {
+    fn step(&mut self) {
+        let next = match (self.rule.named, self.rule.ltl) {
+            (Some(named), _) => self.next_named(named),
+            (None, Some(ltl)) => self.next_larger(&ltl),
+            (None, None) => self.next_cells(),
+        };
+        self.changed = next
+            .iter()
+            .filter(|&(pos, state)| self.cells.get(pos) != Some(state))
+            .map(|(&pos, _)| pos)
+            .chain(self.cells.keys().filter(|pos| !next.contains_key(pos)).copied())
+            .collect();
+        self.cells = next;
+        self.generation += 1;
+    }
+
+    fn get(&self, x: i64, y: i64) -> u8 {
+        self.cells.get(&(x, y)).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, x: i64, y: i64, state: u8) {
+        if self.get(x, y) != state {
+            self.changed.push((x, y));
+        }
+        if state == 0 {
+            self.cells.remove(&(x, y));
+        } else {
+            self.cells.insert((x, y), state);
+        }
+    }
+
+    fn population(&self) -> usize {
+        self.cells.values().filter(|&&state| state == 1).count()
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn cells(&self) -> Vec<((i64, i64), u8)> {
+        self.cells.iter().map(|(&pos, &state)| (pos, state)).collect()
+    }
+
+    fn changed_cells(&self) -> Option<Vec<(i64, i64)>> {
+        let mut changed = self.changed.clone();
+        changed.sort_unstable();
+        changed.dedup();
+        Some(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resources::parse_rule;
+
+    fn universe_with(cells: &[(i64, i64)]) -> SparseUniverse {
+        let mut universe = SparseUniverse::new(Rule::conway());
+        for &(x, y) in cells {
+            universe.set(x, y, 1);
+        }
+        universe
+    }
+
+    #[test]
+    fn test_map_rule_reads_neighbours_by_direction() {
+        // Every cell copies its north-west neighbour.
+        let rule = parse_rule(&format!("MAP{}D{}w", "A".repeat(42), "/".repeat(42))).unwrap();
+        let mut universe = SparseUniverse::new(rule);
+        for &(x, y) in &[(0, 0), (1, 0), (0, 2)] {
+            universe.set(x, y, 1);
+        }
+        universe.step_n(3);
+        let mut cells = universe.cells();
+        cells.sort_unstable();
+        assert_eq!(cells, vec![((3, 3), 1), ((3, 5), 1), ((4, 3), 1)]);
+    }
+
+    #[test]
+    fn test_set_and_get() {
+        let mut universe = SparseUniverse::new(Rule::conway());
+        universe.set(-5_000_000_000, 7, 1);
+        assert_eq!(universe.get(-5_000_000_000, 7), 1);
+        assert_eq!(universe.get(0, 0), 0);
+
+        universe.set(-5_000_000_000, 7, 0);
+        assert_eq!(universe.population(), 0);
+        assert_eq!(universe.bounding_box(), None);
+    }
+
+    #[test]
+    fn test_blinker_oscillates() {
+        let mut universe = universe_with(&[(0, -1), (0, 0), (0, 1)]);
+        universe.step();
+        assert_eq!(universe.population(), 3);
+        assert_eq!(universe.get(-1, 0), 1);
+        assert_eq!(universe.get(1, 0), 1);
+        assert_eq!(universe.get(0, 1), 0);
+        assert_eq!(universe.generation(), 1);
+    }
+
+    #[test]
+    fn test_glider_travels_without_bounds() {
+        let mut universe = universe_with(&[(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)]);
+        universe.step_n(400);
+
+        assert_eq!(universe.population(), 5);
+        assert_eq!(universe.bounding_box(), Some((100, 100, 102, 102)));
+    }
+
+    #[test]
+    fn test_glider_travels_into_negative_coordinates() {
+        let mut universe = universe_with(&[(1, 0), (0, 1), (2, 2), (1, 2), (0, 2)]);
+        universe.step_n(40);
+
+        let (min_x, min_y, _, _) = universe.bounding_box().unwrap();
+        assert!(min_x < 0);
+        assert_eq!(min_y, 10);
+        assert_eq!(universe.population(), 5);
+    }
+
+    #[test]
+    fn test_generations_dying_cells_are_kept() {
+        let mut universe = SparseUniverse::new(parse_rule("B2/S/C3").unwrap());
+        universe.set(0, 0, 1);
+        universe.step();
+        assert_eq!(universe.get(0, 0), 2);
+        assert_eq!(universe.population(), 0);
+        universe.step();
+        assert_eq!(universe.cells().len(), 0);
+    }
+
+    #[test]
+    fn test_larger_than_life_sums_match_conway() {
+        let glider = [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+        let mut conway = universe_with(&glider);
+        let mut larger = SparseUniverse::new(parse_rule("R1,C0,M1,S3..4,B3..3,NM").unwrap());
+        for &(x, y) in &glider {
+            larger.set(x, y, 1);
+        }
+        for _ in 0..8 {
+            conway.step();
+            larger.step();
+            let mut cells = larger.cells();
+            cells.sort_unstable();
+            let mut expected = conway.cells();
+            expected.sort_unstable();
+            assert_eq!(cells, expected);
+        }
+    }
+
+    #[test]
+    fn test_larger_than_life_clusters_far_apart() {
+        let rule = parse_rule("R2,C0,M1,S5..9,B5..7,NM").unwrap();
+        let soup: Vec<(i64, i64)> =
+            (0..12).flat_map(|x| (0..12).map(move |y| (x, y))).filter(|(x, y)| (x * 7 + y * 13) % 5 < 2).collect();
+        let mut single = SparseUniverse::new(rule);
+        let mut apart = SparseUniverse::new(rule);
+        for &(x, y) in &soup {
+            single.set(x, y, 1);
+            apart.set(x, y, 1);
+            apart.set(x + 1_000_000_000, y - 1_000_000_000, 1);
+        }
+        for _ in 0..10 {
+            single.step();
+            apart.step();
+        }
+        assert!(single.population() > 0);
+        assert_eq!(apart.population(), 2 * single.population());
+    }
+
+    #[test]
+    fn test_changed_cells() {
+        let mut universe = universe_with(&[(0, -1), (0, 0), (0, 1)]);
+        universe.step();
+        assert_eq!(universe.changed_cells(), Some(vec![(-1, 0), (0, -1), (0, 1), (1, 0)]));
+        universe.set(0, 0, 0);
+        universe.set(5, 5, 0);
+        assert_eq!(universe.changed_cells().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_from_grid_skips_dead_cells() {
+        let mut grid = Grid::new();
+        grid.insert((0, 0), 0);
+        grid.insert((3, 4), 1);
+        let universe = SparseUniverse::from_grid(&grid, Rule::conway());
+        assert_eq!(universe.cells(), vec![((3, 4), 1)]);
+    }
+}
@@ -1,49 +1,22 @@
 use bracket_lib::prelude::*;
-use legion::systems::CommandBuffer;
 use legion::world::SubWorld;
 use legion::*;
-use std::collections::HashSet;
 
 use crate::components::*;
+use crate::named_rules::NamedRule;
 use crate::resources::*;
 
-const OFFSETS: [(i32, i32); 8] = [
-    (-1, -1), (-1, 0), (-1, 1),
-    (0, -1),           (0, 1),
-    (1, -1),  (1, 0),  (1, 1),
-];
-
+/// Advances the engine one generation and writes each visible cell's new
+/// state into its `NextCell`.
 #[system]
 #[read_component(Position)]
-#[read_component(Cell)]
-pub fn neighbor_counting(world: &mut SubWorld, cmd: &mut CommandBuffer, #[resource] dimensions: &Dimensions, #[resource] rule: &RuleFn) {
-    let width = dimensions.width;
-    let height = dimensions.height;
-
-    let alive: HashSet<(i32, i32)> = <(&Position, &Cell)>::query()
-        .iter(world)
-        .filter_map(|(pos, cell)| {
-            if cell.alive {
-                Some((pos.x, pos.y))
-            } else {
-                None
-            }
-        })
-        .collect();
-
-    let mut query = <(Entity, &Position, &Cell)>::query();
-    for (entity, pos, cell) in query.iter(world) {
-        let mut alive_neighbours = 0;
-        for (dx, dy) in OFFSETS {
-            let nx = pos.x + dx;
-            let ny = pos.y + dy;
-            if nx >= 0 && nx < width && ny >= 0 && ny < height && alive.contains(&(nx, ny)) {
-                alive_neighbours += 1;
-            }
-        }
-        
-        let new_state = rule(cell.alive, alive_neighbours);
-        cmd.add_component(*entity, NextCell { alive: new_state });
+#[write_component(NextCell)]
+pub fn neighbor_counting(world: &mut SubWorld, #[resource] engine: &mut Engine, #[resource] viewport: &Viewport) {
+    engine.0.step();
+
+    let mut query = <(&Position, &mut NextCell)>::query();
+    for (pos, next_cell) in query.iter_mut(world) {
+        next_cell.state = engine.0.get(viewport.x + pos.x as i64, viewport.y + pos.y as i64);
     }
 }
 
@@ -53,7 +26,7 @@ pub fn neighbor_counting(world: &mut SubWorld, cmd: &mut CommandBuffer, #[resour
 pub fn state_update(world: &mut SubWorld) {
     let mut query = <(&NextCell, &mut Cell)>::query();
     for (next_cell, cell) in query.iter_mut(world) {
-        cell.alive = next_cell.alive;
+        cell.state = next_cell.state;
     }
 }
 
@@ -63,7 +36,7 @@ pub fn state_update(world: &mut SubWorld) {
 pub fn age_update(world: &mut SubWorld) {
     let mut query = <(&Cell, &mut Age)>::query();
     for (cell, age) in query.iter_mut(world) {
-        if cell.alive {
+        if cell.is_alive() {
             age.value = age.value.saturating_add(1);
         } else {
             age.value = 0;
@@ -72,11 +45,21 @@ pub fn age_update(world: &mut SubWorld) {
 }
 
 #[system]
+#[read_component(Cell)]
 #[read_component(Age)]
 #[write_component(CellColor)]
-pub fn color_update(world: &mut SubWorld) {
-    let mut query = <(&Age, &mut CellColor)>::query();
-    for (age, color) in query.iter_mut(world) {
+pub fn color_update(world: &mut SubWorld, #[resource] engine: &Engine) {
+    let rule = *engine.0.rule();
+    let mut query = <(&Cell, &Age, &mut CellColor)>::query();
+    for (cell, age, color) in query.iter_mut(world) {
+        if let Some(named) = rule.named {
+            *color = state_color(named, cell.state);
+            continue;
+        }
+        if cell.state > 1 {
+            *color = decay_color(cell.state, rule.states);
+            continue;
+        }
         let age_capped = age.value.min(60);
         let intensity = 255 - (age_capped as f32 * 2.5) as u8;
         color.r = intensity;
@@ -85,39 +68,44 @@ pub fn color_update(world: &mut SubWorld) {
     }
 }
 
-#[system]
-#[write_component(Cell)]
-pub fn mouse_toggle(world: &mut SubWorld, #[resource] input: &InputState, #[resource] index: &PositionIndex) {
-    if !input.mouse_left {
-        return;
-    }
-    
-    if let Some(&entity) = index.0.get(&input.mouse_pos) {
-        if let Ok(mut entry) = world.entry_mut(entity) {
-            if let Ok(cell) = entry.get_component_mut::<Cell>() {
-                cell.alive = !cell.alive;
-            }
-        }
+/// Colors a Generations dying state, fading from orange towards black as
+/// the cell gets closer to dead.
+pub fn decay_color(state: u8, states: u8) -> CellColor {
+    let fade = (state - 1) as f32 / (states - 1) as f32;
+    let level = 255.0 * (1.0 - fade);
+    CellColor {
+        r: level as u8,
+        g: (level * 0.5) as u8,
+        b: 0,
     }
 }
 
-#[system]
-#[read_component(NextCell)]
-pub fn cleanup_next(world: &mut SubWorld, cmd: &mut CommandBuffer) {
-    let mut query = <(Entity, &NextCell)>::query();
-    for (entity, _) in query.iter(world) {
-        cmd.remove_component::<NextCell>(*entity);
-    }
+/// Colors a state of a named rule with the rule's own color for it.
+pub fn state_color(rule: &NamedRule, state: u8) -> CellColor {
+    let (r, g, b) = rule.colours.get(state as usize).copied().unwrap_or_default();
+    CellColor { r, g, b }
 }
 
+/// Moves the clicked cell on to the next state, back to dead after the
+/// last one.
+#[system]
+pub fn mouse_toggle(#[resource] input: &InputState, #[resource] viewport: &Viewport, #[resource] engine: &mut Engine) {
+    if !input.mouse_left {
+        return;
+    }
 
+    let x = viewport.x + input.mouse_pos.0 as i64;
+    let y = viewport.y + input.mouse_pos.1 as i64;
+    let state = (engine.0.get(x, y) + 1) % engine.0.rule().states;
+    engine.0.set(x, y, state);
+}
 
 pub fn render_system(world: &World, ctx: &mut BTerm) {
     ctx.cls();
     
     let mut query = <(&Position, &Cell, &CellColor)>::query();
     for (pos, cell, color) in query.iter(world) {
-        let disp = if cell.alive { 'O' } else { ' ' };
+        let disp = if cell.state != 0 { 'O' } else { ' ' };
         ctx.set(
             pos.x,
             pos.y,
@@ -131,14 +119,13 @@ pub fn render_system(world: &World, ctx: &mut BTerm) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::resources::conway_rule;
+    use crate::universe::{Simulation, Universe};
 
     fn create_test_world() -> (World, Resources) {
         let world = World::default();
         let mut resources = Resources::default();
         
         resources.insert(Dimensions { width: 5, height: 5 });
-        resources.insert(conway_rule as RuleFn);
         
         (world, resources)
     }
@@ -148,13 +135,13 @@ mod tests {
         let (mut world, _resources) = create_test_world();
         
         let entity = world.push((
-            Cell { alive: true },
+            Cell::ALIVE,
             Age { value: 5 },
         ));
 
         let mut query = <(&Cell, &mut Age)>::query();
         for (cell, age) in query.iter_mut(&mut world) {
-            if cell.alive {
+            if cell.is_alive() {
                 age.value = age.value.saturating_add(1);
             } else {
                 age.value = 0;
@@ -171,13 +158,13 @@ mod tests {
         let (mut world, _resources) = create_test_world();
         
         let entity = world.push((
-            Cell { alive: false },
+            Cell::DEAD,
             Age { value: 10 },
         ));
 
         let mut query = <(&Cell, &mut Age)>::query();
         for (cell, age) in query.iter_mut(&mut world) {
-            if cell.alive {
+            if cell.is_alive() {
                 age.value = age.value.saturating_add(1);
             } else {
                 age.value = 0;
@@ -218,80 +205,43 @@ mod tests {
         let (mut world, _resources) = create_test_world();
         
         let entity = world.push((
-            Cell { alive: false },
-            NextCell { alive: true },
+            Cell::DEAD,
+            NextCell { state: 1 },
         ));
 
         let mut query = <(&NextCell, &mut Cell)>::query();
         for (next_cell, cell) in query.iter_mut(&mut world) {
-            cell.alive = next_cell.alive;
+            cell.state = next_cell.state;
         }
 
         let entry = world.entry(entity).unwrap();
         let cell = entry.get_component::<Cell>().unwrap();
-        assert_eq!(cell.alive, true);
+        assert!(cell.is_alive());
     }
 
     #[test]
     fn test_neighbor_counting_corner_cell() {
         let (mut world, mut resources) = create_test_world();
-        
-        world.push((
-            Position { x: 0, y: 0 },
-            Cell { alive: false },
-        ));
-        
-        world.push((
-            Position { x: 1, y: 0 },
-            Cell { alive: true },
-        ));
-        
-        world.push((
-            Position { x: 0, y: 1 },
-            Cell { alive: true },
-        ));
-
-        let mut cmd = CommandBuffer::new(&world);
-        
-        let width = 5;
-        let height = 5;
-        let rule = conway_rule;
-
-        let alive: HashSet<(i32, i32)> = <(&Position, &Cell)>::query()
-            .iter(&world)
-            .filter_map(|(pos, cell)| {
-                if cell.alive {
-                    Some((pos.x, pos.y))
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        let mut query = <(Entity, &Position, &Cell)>::query();
-        for (entity, pos, cell) in query.iter(&world) {
-            let mut alive_neighbours = 0;
-            for (dx, dy) in OFFSETS {
-                let nx = pos.x + dx;
-                let ny = pos.y + dy;
-                if nx >= 0 && nx < width && ny >= 0 && ny < height && alive.contains(&(nx, ny)) {
-                    alive_neighbours += 1;
-                }
-            }
-            
-            let new_state = rule(cell.alive, alive_neighbours);
-            cmd.add_component(*entity, NextCell { alive: new_state });
+        let dimensions = Dimensions { width: 5, height: 5 };
+        let mut universe = Universe::new(dimensions, Topology::Plane, Rule::conway());
+        for (x, y) in [(1, 0), (0, 1), (1, 1)] {
+            universe.set(x, y, 1);
         }
-        
-        cmd.flush(&mut world, &mut resources);
-        
-        let query_result: Vec<(bool, i32)> = <(&Cell, &NextCell, &Position)>::query()
-            .iter(&world)
-            .filter(|(_, _, pos)| pos.x == 0 && pos.y == 0)
-            .map(|(cell, next, _)| (cell.alive, if next.alive { 1 } else { 0 }))
-            .collect();
-        
-        assert_eq!(query_result.len(), 1);
+        resources.insert(Engine(Box::new(universe)));
+        resources.insert(Viewport::default());
+        for (x, y) in [(0, 0), (1, 1), (4, 4)] {
+            world.push((Position { x, y }, NextCell { state: 0 }));
+        }
+
+        let mut schedule = Schedule::builder().add_system(neighbor_counting_system()).build();
+        schedule.execute(&mut world, &mut resources);
+
+        // The corner cell has three live neighbours and is born.
+        let mut next: Vec<(i32, i32, u8)> =
+            <(&Position, &NextCell)>::query().iter(&world).map(|(pos, next)| (pos.x, pos.y, next.state)).collect();
+        next.sort_unstable();
+        assert_eq!(next, vec![(0, 0, 1), (1, 1, 1), (4, 4, 0)]);
+        assert_eq!(resources.get::<Engine>().unwrap().0.generation(), 1);
     }
 
     #[test]
@@ -317,5 +267,21 @@ mod tests {
         assert_eq!(color.r, 105);
         assert_eq!(color.b, 255);
     }
-}
 
+    #[test]
+    fn test_state_color_follows_the_named_rule() {
+        let wireworld = crate::named_rules::find("WireWorld").unwrap();
+        assert_eq!(state_color(wireworld, 3), CellColor { r: 255, g: 128, b: 0 });
+        assert_ne!(state_color(wireworld, 1), state_color(wireworld, 2));
+    }
+
+    #[test]
+    fn test_decay_color_fades_with_each_state() {
+        let first = decay_color(2, 5);
+        let last = decay_color(4, 5);
+        assert!(first.r > last.r);
+        assert!(first.g > last.g);
+        assert_eq!(first.b, 0);
+        assert_ne!(decay_color(3, 5), first);
+    }
+}
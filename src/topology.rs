@@ -0,0 +1,366 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::resources::Dimensions;
+
+/// How the edges of the board are joined.
+///
+/// These are the bounded grids Golly supports. Diagonal neighbours across a
+/// corner of a cross-surface or a sphere have no well-defined twin and are
+/// treated as dead.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Topology {
+    /// Cells beyond the edges are always dead.
+    #[default]
+    Plane,
+    /// Opposite edges are joined. A horizontal shift slides the top and
+    /// bottom edges against each other, a vertical shift the left and right
+    /// edges; at most one of them is non-zero.
+    Torus { horizontal_shift: i32, vertical_shift: i32 },
+    /// Opposite edges are joined, one pair of them with a twist: the top and
+    /// bottom edges when `horizontal_twist` is set, else the left and right.
+    KleinBottle { horizontal_twist: bool },
+    /// Both pairs of opposite edges are joined with a twist.
+    CrossSurface,
+    /// The top edge is joined to the left edge and the bottom edge to the
+    /// right edge. Only square boards can be spheres.
+    Sphere,
+}
+
+impl Topology {
+    pub const TORUS: Topology = Topology::Torus {
+        horizontal_shift: 0,
+        vertical_shift: 0,
+    };
+
+    /// Maps a neighbour position onto the board, or returns `None` when it
+    /// falls off the edge.
+    pub fn wrap(&self, x: i32, y: i32, dimensions: &Dimensions) -> Option<(i32, i32)> {
+        let (width, height) = (dimensions.width, dimensions.height);
+        let x_inside = x >= 0 && x < width;
+        let y_inside = y >= 0 && y < height;
+        if x_inside && y_inside {
+            return Some((x, y));
+        }
+
+        let wrapped = match *self {
+            Topology::Plane => None,
+            Topology::Torus {
+                horizontal_shift,
+                vertical_shift,
+            } => {
+                let x_shifted = x + horizontal_shift * y.div_euclid(height);
+                let y_shifted = y + vertical_shift * x.div_euclid(width);
+                Some((x_shifted.rem_euclid(width), y_shifted.rem_euclid(height)))
+            }
+            Topology::KleinBottle { horizontal_twist } => {
+                let (mut x, mut y) = (x.rem_euclid(width), y.rem_euclid(height));
+                if horizontal_twist && !y_inside {
+                    x = width - 1 - x;
+                }
+                if !horizontal_twist && !x_inside {
+                    y = height - 1 - y;
+                }
+                Some((x, y))
+            }
+            Topology::CrossSurface if x_inside => Some((width - 1 - x, y.rem_euclid(height))),
+            Topology::CrossSurface if y_inside => Some((x.rem_euclid(width), height - 1 - y)),
+            Topology::Sphere if x_inside && y < 0 => Some((-1 - y, x)),
+            Topology::Sphere if x_inside => Some((width - 1 - (y - height), x)),
+            Topology::Sphere if y_inside && x < 0 => Some((y, -1 - x)),
+            Topology::Sphere if y_inside => Some((y, height - 1 - (x - width))),
+            Topology::CrossSurface | Topology::Sphere => None,
+        };
+        // Cells further past the edge of a sphere than it is wide fall off.
+        wrapped.filter(|&(x, y)| x >= 0 && x < width && y >= 0 && y < height)
+    }
+}
+
+impl FromStr for Topology {
+    type Err = TopologyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "plane" => Ok(Topology::Plane),
+            "torus" => Ok(Topology::TORUS),
+            _ => Err(TopologyParseError::Unknown(s.to_string())),
+        }
+    }
+}
+
+/// A Golly bounded grid spec such as `T80,50`, `K80*,50` or `T80+2,50`: a
+/// topology together with the board size it applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BoundedGrid {
+    pub topology: Topology,
+    pub dimensions: Dimensions,
+}
+
+/// One side of a bounded grid spec, e.g. `80*` or `80+2`.
+struct Edge {
+    length: i32,
+    twist: bool,
+    shift: i32,
+}
+
+impl FromStr for BoundedGrid {
+    type Err = TopologyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let spec = s.trim();
+        let mut chars = spec.chars();
+        let kind = chars.next().map(|c| c.to_ascii_uppercase());
+        if !matches!(kind, Some('P' | 'T' | 'K' | 'C' | 'S')) {
+            return Err(TopologyParseError::Unknown(spec.to_string()));
+        }
+        let mut sides = chars.as_str().split(',');
+        let width_edge = parse_edge(sides.next().unwrap_or_default(), spec)?;
+        let height_edge = match sides.next() {
+            Some(side) => parse_edge(side, spec)?,
+            None if kind == Some('S') => Edge { length: width_edge.length, twist: false, shift: 0 },
+            None => return Err(TopologyParseError::InvalidSize(spec.to_string())),
+        };
+        if sides.next().is_some() {
+            return Err(TopologyParseError::InvalidSize(spec.to_string()));
+        }
+
+        let twists = (width_edge.twist, height_edge.twist);
+        let shifts = (width_edge.shift, height_edge.shift);
+        let topology = match kind {
+            Some('P') => Topology::Plane,
+            Some('T') => Topology::Torus {
+                horizontal_shift: width_edge.shift,
+                vertical_shift: height_edge.shift,
+            },
+            Some('K') => match twists {
+                (true, false) => Topology::KleinBottle { horizontal_twist: true },
+                (false, true) => Topology::KleinBottle { horizontal_twist: false },
+                _ => return Err(TopologyParseError::InvalidTwist(spec.to_string())),
+            },
+            Some('C') => Topology::CrossSurface,
+            Some('S') if width_edge.length == height_edge.length => Topology::Sphere,
+            _ => return Err(TopologyParseError::NotSquare(spec.to_string())),
+        };
+
+        if twists != (false, false) && !matches!(topology, Topology::KleinBottle { .. }) {
+            return Err(TopologyParseError::InvalidTwist(spec.to_string()));
+        }
+        if shifts != (0, 0) && !(matches!(topology, Topology::Torus { .. }) && (shifts.0 == 0 || shifts.1 == 0)) {
+            return Err(TopologyParseError::InvalidShift(spec.to_string()));
+        }
+
+        Ok(BoundedGrid {
+            topology,
+            dimensions: Dimensions {
+                width: width_edge.length,
+                height: height_edge.length,
+            },
+        })
+    }
+}
+
+fn parse_edge(side: &str, spec: &str) -> Result<Edge, TopologyParseError> {
+    let digits_end = side.find(|c: char| !c.is_ascii_digit()).unwrap_or(side.len());
+    let length = match side[..digits_end].parse::<i32>() {
+        Ok(length) if length > 0 => length,
+        _ => return Err(TopologyParseError::InvalidSize(spec.to_string())),
+    };
+
+    let rest = &side[digits_end..];
+    let (twist, rest) = match rest.strip_prefix('*') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let shift = if rest.is_empty() {
+        0
+    } else if rest.starts_with(['+', '-']) {
+        rest.parse::<i32>()
+            .map_err(|_| TopologyParseError::InvalidShift(spec.to_string()))?
+    } else {
+        return Err(TopologyParseError::InvalidSize(spec.to_string()));
+    };
+
+    Ok(Edge { length, twist, shift })
+}
+
+impl fmt::Display for BoundedGrid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Dimensions { width, height } = self.dimensions;
+        let write_shift = |f: &mut fmt::Formatter, shift: i32| if shift != 0 { write!(f, "{:+}", shift) } else { Ok(()) };
+        match self.topology {
+            Topology::Plane => write!(f, "P{},{}", width, height),
+            Topology::Torus {
+                horizontal_shift,
+                vertical_shift,
+            } => {
+                write!(f, "T{}", width)?;
+                write_shift(f, horizontal_shift)?;
+                write!(f, ",{}", height)?;
+                write_shift(f, vertical_shift)
+            }
+            Topology::KleinBottle { horizontal_twist: true } => write!(f, "K{}*,{}", width, height),
+            Topology::KleinBottle { horizontal_twist: false } => write!(f, "K{},{}*", width, height),
+            Topology::CrossSurface => write!(f, "C{},{}", width, height),
+            Topology::Sphere => write!(f, "S{}", width),
+        }
+    }
+}
+
+/// Parses a topology argument: either a name (`plane`, `torus`) applied to
+/// a board of the given dimensions, or a bounded grid spec with its own
+/// size.
+pub fn parse_topology(s: &str, dimensions: Dimensions) -> Result<BoundedGrid, TopologyParseError> {
+    match s.parse::<Topology>() {
+        Ok(topology) => Ok(BoundedGrid { topology, dimensions }),
+        Err(_) => s.parse::<BoundedGrid>(),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TopologyParseError {
+    Unknown(String),
+    InvalidSize(String),
+    InvalidTwist(String),
+    InvalidShift(String),
+    NotSquare(String),
+}
+
+impl fmt::Display for TopologyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TopologyParseError::Unknown(s) => write!(f, "unknown topology '{}'", s),
+            TopologyParseError::InvalidSize(s) => write!(f, "invalid grid size in '{}'", s),
+            TopologyParseError::InvalidTwist(s) => write!(f, "invalid edge twist in '{}'", s),
+            TopologyParseError::InvalidShift(s) => write!(f, "invalid edge shift in '{}'", s),
+            TopologyParseError::NotSquare(s) => write!(f, "sphere '{}' must be square", s),
+        }
+    }
+}
+
+impl Error for TopologyParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIMENSIONS: Dimensions = Dimensions { width: 5, height: 4 };
+
+    fn grid(spec: &str) -> BoundedGrid {
+        spec.parse().unwrap()
+    }
+
+    #[test]
+    fn test_plane_topology_drops_outside_positions() {
+        assert_eq!(Topology::Plane.wrap(0, 3, &DIMENSIONS), Some((0, 3)));
+        assert_eq!(Topology::Plane.wrap(-1, 0, &DIMENSIONS), None);
+        assert_eq!(Topology::Plane.wrap(5, 0, &DIMENSIONS), None);
+        assert_eq!(Topology::Plane.wrap(0, 4, &DIMENSIONS), None);
+    }
+
+    #[test]
+    fn test_torus_topology_wraps_both_axes() {
+        assert_eq!(Topology::TORUS.wrap(2, 2, &DIMENSIONS), Some((2, 2)));
+        assert_eq!(Topology::TORUS.wrap(-1, 0, &DIMENSIONS), Some((4, 0)));
+        assert_eq!(Topology::TORUS.wrap(5, 4, &DIMENSIONS), Some((0, 0)));
+        assert_eq!(Topology::TORUS.wrap(-1, -1, &DIMENSIONS), Some((4, 3)));
+    }
+
+    #[test]
+    fn test_shifted_torus_slides_crossed_edges() {
+        let horizontal = Topology::Torus { horizontal_shift: 2, vertical_shift: 0 };
+        assert_eq!(horizontal.wrap(1, 4, &DIMENSIONS), Some((3, 0)));
+        assert_eq!(horizontal.wrap(1, -1, &DIMENSIONS), Some((4, 3)));
+        assert_eq!(horizontal.wrap(-1, 2, &DIMENSIONS), Some((4, 2)));
+
+        let vertical = Topology::Torus { horizontal_shift: 0, vertical_shift: 1 };
+        assert_eq!(vertical.wrap(5, 3, &DIMENSIONS), Some((0, 0)));
+        assert_eq!(vertical.wrap(-1, 0, &DIMENSIONS), Some((4, 3)));
+    }
+
+    #[test]
+    fn test_klein_bottle_twists_one_pair_of_edges() {
+        let horizontal = Topology::KleinBottle { horizontal_twist: true };
+        assert_eq!(horizontal.wrap(1, -1, &DIMENSIONS), Some((3, 3)));
+        assert_eq!(horizontal.wrap(-1, 1, &DIMENSIONS), Some((4, 1)));
+        assert_eq!(horizontal.wrap(-1, -1, &DIMENSIONS), Some((0, 3)));
+
+        let vertical = Topology::KleinBottle { horizontal_twist: false };
+        assert_eq!(vertical.wrap(-1, 0, &DIMENSIONS), Some((4, 3)));
+        assert_eq!(vertical.wrap(1, 4, &DIMENSIONS), Some((1, 0)));
+    }
+
+    #[test]
+    fn test_cross_surface_twists_both_pairs_of_edges() {
+        assert_eq!(Topology::CrossSurface.wrap(1, -1, &DIMENSIONS), Some((3, 3)));
+        assert_eq!(Topology::CrossSurface.wrap(5, 1, &DIMENSIONS), Some((0, 2)));
+        assert_eq!(Topology::CrossSurface.wrap(-1, -1, &DIMENSIONS), None);
+    }
+
+    #[test]
+    fn test_sphere_joins_adjacent_edges() {
+        let square = Dimensions { width: 4, height: 4 };
+        assert_eq!(Topology::Sphere.wrap(2, -1, &square), Some((0, 2)));
+        assert_eq!(Topology::Sphere.wrap(-1, 2, &square), Some((2, 0)));
+        assert_eq!(Topology::Sphere.wrap(1, 4, &square), Some((3, 1)));
+        assert_eq!(Topology::Sphere.wrap(4, 1, &square), Some((1, 3)));
+        assert_eq!(Topology::Sphere.wrap(-1, -1, &square), None);
+        // Cells further past an edge reach further into the board.
+        assert_eq!(Topology::Sphere.wrap(2, -2, &square), Some((1, 2)));
+        assert_eq!(Topology::Sphere.wrap(5, 1, &square), Some((1, 2)));
+        assert_eq!(Topology::Sphere.wrap(2, -5, &square), None);
+    }
+
+    #[test]
+    fn test_parse_topology_name() {
+        assert_eq!("plane".parse(), Ok(Topology::Plane));
+        assert_eq!("Torus".parse(), Ok(Topology::TORUS));
+        assert_eq!("donut".parse::<Topology>(), Err(TopologyParseError::Unknown("donut".to_string())));
+    }
+
+    #[test]
+    fn test_parse_bounded_grid_specs() {
+        let size = Dimensions { width: 80, height: 50 };
+        assert_eq!(grid("P80,50"), BoundedGrid { topology: Topology::Plane, dimensions: size });
+        assert_eq!(grid("T80,50"), BoundedGrid { topology: Topology::TORUS, dimensions: size });
+        assert_eq!(grid("t80+2,50").topology, Topology::Torus { horizontal_shift: 2, vertical_shift: 0 });
+        assert_eq!(grid("T80,50-3").topology, Topology::Torus { horizontal_shift: 0, vertical_shift: -3 });
+        assert_eq!(grid("K80*,50").topology, Topology::KleinBottle { horizontal_twist: true });
+        assert_eq!(grid("K80,50*").topology, Topology::KleinBottle { horizontal_twist: false });
+        assert_eq!(grid("C80,50").topology, Topology::CrossSurface);
+        assert_eq!(
+            grid("S80"),
+            BoundedGrid { topology: Topology::Sphere, dimensions: Dimensions { width: 80, height: 80 } }
+        );
+    }
+
+    #[test]
+    fn test_parse_bounded_grid_errors() {
+        let invalid_size = |s: &str| Err(TopologyParseError::InvalidSize(s.to_string()));
+        assert_eq!("X80,50".parse::<BoundedGrid>(), Err(TopologyParseError::Unknown("X80,50".to_string())));
+        assert_eq!("T80".parse::<BoundedGrid>(), invalid_size("T80"));
+        assert_eq!("T0,50".parse::<BoundedGrid>(), invalid_size("T0,50"));
+        assert_eq!("T80,50,2".parse::<BoundedGrid>(), invalid_size("T80,50,2"));
+        assert_eq!("Tx,50".parse::<BoundedGrid>(), invalid_size("Tx,50"));
+        assert_eq!("K80,50".parse::<BoundedGrid>(), Err(TopologyParseError::InvalidTwist("K80,50".to_string())));
+        assert_eq!("T80*,50".parse::<BoundedGrid>(), Err(TopologyParseError::InvalidTwist("T80*,50".to_string())));
+        assert_eq!("T80+1,50+1".parse::<BoundedGrid>(), Err(TopologyParseError::InvalidShift("T80+1,50+1".to_string())));
+        assert_eq!("C80+1,50".parse::<BoundedGrid>(), Err(TopologyParseError::InvalidShift("C80+1,50".to_string())));
+        assert_eq!("S80,50".parse::<BoundedGrid>(), Err(TopologyParseError::NotSquare("S80,50".to_string())));
+    }
+
+    #[test]
+    fn test_bounded_grid_display_round_trips() {
+        for spec in ["P80,50", "T80,50", "T80+2,50", "T80,50-1", "K80*,50", "K80,50*", "C80,50", "S80"] {
+            assert_eq!(grid(spec).to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn test_parse_topology_accepts_names_and_specs() {
+        let board = Dimensions { width: 10, height: 10 };
+        assert_eq!(parse_topology("torus", board), Ok(BoundedGrid { topology: Topology::TORUS, dimensions: board }));
+        assert_eq!(parse_topology("C20,30", board).unwrap().dimensions, Dimensions { width: 20, height: 30 });
+        assert!(parse_topology("donut", board).is_err());
+    }
+}
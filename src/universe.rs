@@ -0,0 +1,766 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use rayon::prelude::*;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+
+use crate::bitgrid::BitUniverse;
+use crate::hashlife::HashLife;
+use crate::ltl::{Ltl, SummedArea};
+use crate::resources::{neighbour_bit, Dimensions, Rule, RuleKind, Topology};
+use crate::seeder::Grid;
+use crate::sparse::SparseUniverse;
+
+pub const OFFSETS: [(i32, i32); 8] = [
+    (-1, -1), (-1, 0), (-1, 1),
+    (0, -1),           (0, 1),
+    (1, -1),  (1, 0),  (1, 1),
+];
+
+/// A cellular automaton that can be stepped without any rendering or ECS
+/// machinery. Cell states follow `Rule::next_state_for`: 0 is dead, 1 is alive
+/// and anything above is a dying Generations state.
+pub trait Simulation: Send + Sync {
+    fn step(&mut self);
+
+    fn step_n(&mut self, generations: u64) {
+        for _ in 0..generations {
+            self.step();
+        }
+    }
+
+    fn get(&self, x: i64, y: i64) -> u8;
+
+    fn set(&mut self, x: i64, y: i64, state: u8);
+
+    /// Number of live cells; dying Generations states are not counted.
+    fn population(&self) -> usize;
+
+    fn generation(&self) -> u64;
+
+    fn rule(&self) -> &Rule;
+
+    /// Every cell that is not dead, in no particular order.
+    fn cells(&self) -> Vec<((i64, i64), u8)>;
+
+    /// Cells whose state differs from the previous generation, including
+    /// ones changed with `set` since, each listed once. `None` when the
+    /// engine does not track changes.
+    fn changed_cells(&self) -> Option<Vec<(i64, i64)>> {
+        None
+    }
+}
+
+/// The implementations a bounded board can be simulated with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// `Universe`: one byte per cell, supports every rule and topology.
+    #[default]
+    Cells,
+    /// `BitUniverse`: 64 cells per word, much faster on large boards.
+    Bits,
+    /// `HashLife`: memoized quadtree for huge jumps on the unbounded plane.
+    HashLife,
+}
+
+impl Backend {
+    pub fn build(
+        &self,
+        grid: &Grid,
+        dimensions: Dimensions,
+        topology: Topology,
+        rule: Rule,
+        threads: usize,
+    ) -> Result<Box<dyn Simulation>, EngineError> {
+        Ok(match self {
+            Backend::Cells => Box::new(Universe::from_grid(grid, dimensions, topology, rule).with_threads(threads)),
+            Backend::Bits => Box::new(BitUniverse::from_grid(grid, dimensions, topology, rule)?.with_threads(threads)),
+            Backend::HashLife => return Err(EngineError::UnsupportedTopology(topology)),
+        })
+    }
+
+    pub fn build_unbounded(&self, grid: &Grid, rule: Rule) -> Result<Box<dyn Simulation>, EngineError> {
+        Ok(match self {
+            Backend::Cells if rule.has_b0() => return Err(EngineError::UnsupportedRule(rule.to_string())),
+            Backend::Cells => Box::new(SparseUniverse::from_grid(grid, rule)),
+            Backend::Bits => return Err(EngineError::Unbounded),
+            Backend::HashLife => Box::new(HashLife::from_grid(grid, rule)?),
+        })
+    }
+}
+
+impl FromStr for Backend {
+    type Err = EngineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "cells" => Ok(Backend::Cells),
+            "bits" => Ok(Backend::Bits),
+            "hashlife" => Ok(Backend::HashLife),
+            _ => Err(EngineError::UnknownBackend(s.to_string())),
+        }
+    }
+}
+
+/// Computes the rows or cells of a board's next generation, either one
+/// after the other or spread over a pool of threads. Every cell only reads
+/// the previous generation, so both orders give identical boards.
+#[derive(Default)]
+pub struct Workers(Option<ThreadPool>);
+
+impl Workers {
+    /// `threads` of 1 steps serially and 0 uses every core.
+    pub fn new(threads: usize) -> Self {
+        match threads {
+            1 => Workers(None),
+            _ => Workers(ThreadPoolBuilder::new().num_threads(threads).build().ok()),
+        }
+    }
+
+    pub fn for_each_row<T: Send>(&self, cells: &mut [T], width: usize, update: impl Fn(usize, &mut [T]) + Sync) {
+        match &self.0 {
+            None => cells.chunks_mut(width).enumerate().for_each(|(y, row)| update(y, row)),
+            Some(pool) => pool.install(|| cells.par_chunks_mut(width).enumerate().for_each(|(y, row)| update(y, row))),
+        }
+    }
+
+    /// `f` of each item, in order.
+    pub fn map<T: Sync, R: Send>(&self, items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+        match &self.0 {
+            None => items.iter().map(f).collect(),
+            Some(pool) => pool.install(|| items.par_iter().map(&f).collect()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EngineError {
+    UnknownBackend(String),
+    UnsupportedRule(String),
+    UnsupportedTopology(Topology),
+    Unbounded,
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EngineError::UnknownBackend(s) => write!(f, "unknown engine '{}'", s),
+            EngineError::UnsupportedRule(rule) => write!(f, "engine does not support rule {}", rule),
+            EngineError::UnsupportedTopology(topology) => write!(f, "engine does not support topology {:?}", topology),
+            EngineError::Unbounded => write!(f, "engine does not support the unbounded plane"),
+        }
+    }
+}
+
+impl Error for EngineError {}
+
+/// Every rotation and reflection of `offsets`.
+fn reach(offsets: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    let mut reach = Vec::with_capacity(offsets.len());
+    for &(dx, dy) in offsets {
+        for (sx, sy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            for offset in [(sx * dx, sy * dy), (sx * dy, sy * dx)] {
+                if !reach.contains(&offset) {
+                    reach.push(offset);
+                }
+            }
+        }
+    }
+    reach
+}
+
+/// A fixed-size board whose edges are joined according to its topology.
+/// Positions outside the board read as dead and ignore writes.
+///
+/// A cell whose neighbourhood did not change last generation cannot change
+/// this one, so only the cells that changed and their neighbours are
+/// re-evaluated. The whole board is evaluated when most of it is active.
+pub struct Universe {
+    dimensions: Dimensions,
+    topology: Topology,
+    rule: Rule,
+    /// The rule's neighbourhood, whose cells are counted.
+    offsets: Vec<(i32, i32)>,
+    /// The neighbourhood turned and mirrored every way a topology's seams
+    /// can, so a changed cell reaches every cell that might count it.
+    reach: Vec<(i32, i32)>,
+    cells: Vec<u8>,
+    next: Vec<u8>,
+    generation: u64,
+    workers: Workers,
+    /// Indices changed since the last step, possibly repeated.
+    changed: Vec<usize>,
+    /// Set until the first full evaluation, as B0 rules change cells whose
+    /// neighbourhood never did.
+    stale: bool,
+    marked: Vec<bool>,
+}
+
+impl Universe {
+    pub fn new(dimensions: Dimensions, topology: Topology, rule: Rule) -> Self {
+        let size = (dimensions.width * dimensions.height) as usize;
+        Universe {
+            dimensions,
+            topology,
+            rule,
+            offsets: rule.offsets(),
+            reach: reach(&rule.offsets()),
+            cells: vec![0; size],
+            next: vec![0; size],
+            generation: 0,
+            workers: Workers::default(),
+            changed: Vec::new(),
+            stale: true,
+            marked: vec![false; size],
+        }
+    }
+
+    pub fn from_grid(grid: &Grid, dimensions: Dimensions, topology: Topology, rule: Rule) -> Self {
+        let mut universe = Universe::new(dimensions, topology, rule);
+        for (&(x, y), &state) in grid {
+            universe.set(x as i64, y as i64, state as u8);
+        }
+        universe
+    }
+
+    /// Steps with `threads` threads, see `Workers::new`.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.workers = Workers::new(threads);
+        self
+    }
+
+    pub fn dimensions(&self) -> Dimensions {
+        self.dimensions
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    fn index(&self, x: i64, y: i64) -> Option<usize> {
+        let (width, height) = (self.dimensions.width as i64, self.dimensions.height as i64);
+        if x >= 0 && x < width && y >= 0 && y < height {
+            Some((y * width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn live_neighbours(&self, x: i32, y: i32) -> i32 {
+        self.offsets
+            .iter()
+            .filter_map(|(dx, dy)| self.topology.wrap(x + dx, y + dy, &self.dimensions))
+            .filter(|&(nx, ny)| self.cells[(ny * self.dimensions.width + nx) as usize] == 1)
+            .count() as i32
+    }
+
+    /// The live neighbours of `(x, y)` as a `Rule::next_state_for` index.
+    pub fn neighbourhood(&self, x: i32, y: i32) -> u16 {
+        OFFSETS
+            .iter()
+            .filter(|(dx, dy)| {
+                self.topology
+                    .wrap(x + dx, y + dy, &self.dimensions)
+                    .is_some_and(|(nx, ny)| self.cells[(ny * self.dimensions.width + nx) as usize] == 1)
+            })
+            .fold(0, |neighbourhood, &(dx, dy)| neighbourhood | neighbour_bit(dx, dy))
+    }
+
+    /// The cells that may count `(x, y)` in their neighbourhood.
+    fn neighbours(&self, x: i32, y: i32) -> impl Iterator<Item = usize> + '_ {
+        self.reach
+            .iter()
+            .filter_map(move |(dx, dy)| self.topology.wrap(x + dx, y + dy, &self.dimensions))
+            .map(move |(nx, ny)| (ny * self.dimensions.width + nx) as usize)
+    }
+
+    fn next_state(&self, x: i32, y: i32) -> u8 {
+        let cell = self.cells[(y * self.dimensions.width + x) as usize];
+        if let Some(named) = self.rule.named {
+            let mut neighbours = [0; 8];
+            for (state, (dx, dy)) in neighbours.iter_mut().zip(&self.offsets) {
+                *state = self
+                    .topology
+                    .wrap(x + dx, y + dy, &self.dimensions)
+                    .map_or(0, |(nx, ny)| self.cells[(ny * self.dimensions.width + nx) as usize]);
+            }
+            (named.next_state)(cell, &neighbours[..self.offsets.len()])
+        } else if self.rule.kind() == RuleKind::LargerThanLife || self.rule.radius() > 1 {
+            self.rule.next_state(cell, self.live_neighbours(x, y))
+        } else {
+            self.rule.next_state_for(cell, self.neighbourhood(x, y))
+        }
+    }
+
+    fn position(&self, index: usize) -> (i32, i32) {
+        let width = self.dimensions.width as usize;
+        ((index % width) as i32, (index / width) as i32)
+    }
+
+    /// The summed-area table of the board and as much of its wrapped
+    /// surroundings as an LtL neighbourhood reaches.
+    fn summed_area(&self, ltl: &Ltl) -> SummedArea {
+        let radius = ltl.radius as usize;
+        let (width, height) = (self.dimensions.width as usize, self.dimensions.height as usize);
+        let corner = (-(radius as i64), -(radius as i64));
+        SummedArea::new(ltl, corner, width + 2 * radius, height + 2 * radius, |x, y| {
+            self.topology
+                .wrap(x as i32, y as i32, &self.dimensions)
+                .is_some_and(|(nx, ny)| self.cells[(ny * self.dimensions.width + nx) as usize] == 1)
+        })
+    }
+
+    fn step_everything(&mut self) {
+        let width = self.dimensions.width;
+        let mut next = std::mem::take(&mut self.next);
+        let sums = self.rule.ltl.map(|ltl| self.summed_area(&ltl));
+        self.workers.for_each_row(&mut next, width as usize, |y, row| {
+            for (x, cell) in row.iter_mut().enumerate() {
+                let (x, y) = (x as i32, y as i32);
+                *cell = match &sums {
+                    Some(sums) => {
+                        let state = self.cells[(y * width + x) as usize];
+                        self.rule.next_state(state, sums.count(x as i64, y as i64))
+                    }
+                    None => self.next_state(x, y),
+                };
+            }
+        });
+        self.next = std::mem::replace(&mut self.cells, next);
+        self.changed = (0..self.cells.len()).filter(|&index| self.cells[index] != self.next[index]).collect();
+        self.stale = false;
+    }
+
+    fn step_changed(&mut self) {
+        let mut marked = std::mem::take(&mut self.marked);
+        let mut candidates = Vec::with_capacity(self.changed.len() * (self.reach.len() + 1));
+        for &index in &self.changed {
+            let (x, y) = self.position(index);
+            for candidate in std::iter::once(index).chain(self.neighbours(x, y)) {
+                if !marked[candidate] {
+                    marked[candidate] = true;
+                    candidates.push(candidate);
+                }
+            }
+        }
+
+        let updates: Vec<(usize, u8)> = self
+            .workers
+            .map(&candidates, |&index| {
+                let (x, y) = self.position(index);
+                (index, self.next_state(x, y))
+            })
+            .into_iter()
+            .filter(|&(index, state)| state != self.cells[index])
+            .collect();
+        for index in candidates {
+            marked[index] = false;
+        }
+        self.marked = marked;
+        self.changed = updates.iter().map(|&(index, _)| index).collect();
+        for (index, state) in updates {
+            self.cells[index] = state;
+        }
+    }
+}
+
+impl Simulation for Universe {
+    fn step(&mut self) {
+        // Larger than Life neighbourhoods are too wide for the active region
+        // to save anything over summing the whole board. Each changed cell
+        // puts itself and its reach up for evaluation.
+        let candidates = self.changed.len() * (self.reach.len() + 1);
+        if self.stale || self.rule.kind() == RuleKind::LargerThanLife || candidates > self.cells.len() / 2 {
+            self.step_everything();
+        } else {
+            self.step_changed();
+        }
+        self.generation += 1;
+    }
+
+    fn get(&self, x: i64, y: i64) -> u8 {
+        self.index(x, y).map_or(0, |index| self.cells[index])
+    }
+
+    fn set(&mut self, x: i64, y: i64, state: u8) {
+        if let Some(index) = self.index(x, y) {
+            if self.cells[index] != state {
+                self.cells[index] = state;
+                self.changed.push(index);
+            }
+        }
+    }
+
+    fn population(&self) -> usize {
+        self.cells.iter().filter(|&&state| state == 1).count()
+    }
+
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn rule(&self) -> &Rule {
+        &self.rule
+    }
+
+    fn cells(&self) -> Vec<((i64, i64), u8)> {
+        (0..self.cells.len())
+            .filter(|&index| self.cells[index] != 0)
+            .map(|index| {
+                let (x, y) = self.position(index);
+                ((x as i64, y as i64), self.cells[index])
+            })
+            .collect()
+    }
+
+    fn changed_cells(&self) -> Option<Vec<(i64, i64)>> {
+        let mut changed = self.changed.clone();
+        changed.sort_unstable();
+        changed.dedup();
+        Some(
+            changed
+                .into_iter()
+                .map(|index| self.position(index))
+                .map(|(x, y)| (x as i64, y as i64))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn universe_with(cells: &[(i64, i64)], width: i32, height: i32, topology: Topology) -> Universe {
+        let mut universe = Universe::new(Dimensions { width, height }, topology, Rule::conway());
+        for &(x, y) in cells {
+            universe.set(x, y, 1);
+        }
+        universe
+    }
+
+    #[test]
+    fn test_new_universe_is_empty() {
+        let universe = Universe::new(Dimensions { width: 4, height: 3 }, Topology::Plane, Rule::conway());
+        assert_eq!(universe.population(), 0);
+        assert_eq!(universe.generation(), 0);
+    }
+
+    #[test]
+    fn test_out_of_range_positions_read_dead_and_ignore_writes() {
+        let mut universe = universe_with(&[], 4, 3, Topology::Plane);
+        universe.set(4, 0, 1);
+        universe.set(-1, 2, 1);
+        assert_eq!(universe.population(), 0);
+        assert_eq!(universe.get(4, 0), 0);
+    }
+
+    #[test]
+    fn test_blinker_oscillates() {
+        let mut universe = universe_with(&[(2, 1), (2, 2), (2, 3)], 5, 5, Topology::Plane);
+        universe.step();
+        assert_eq!(universe.get(1, 2), 1);
+        assert_eq!(universe.get(3, 2), 1);
+        assert_eq!(universe.get(2, 1), 0);
+        universe.step_n(2);
+        assert_eq!(universe.get(1, 2), 1);
+        assert_eq!(universe.population(), 3);
+        assert_eq!(universe.generation(), 3);
+    }
+
+    #[test]
+    fn test_live_neighbours_respect_topology() {
+        let corners = [(0, 0), (3, 0), (0, 3)];
+        let plane = universe_with(&corners, 4, 4, Topology::Plane);
+        let torus = universe_with(&corners, 4, 4, Topology::TORUS);
+        assert_eq!(plane.live_neighbours(3, 3), 0);
+        assert_eq!(torus.live_neighbours(3, 3), 3);
+    }
+
+    #[test]
+    fn test_neighbourhood_sets_a_bit_per_live_neighbour() {
+        let universe = universe_with(&[(1, 0), (0, 1), (2, 2), (1, 1)], 3, 3, Topology::Plane);
+        let expected = neighbour_bit(0, -1) | neighbour_bit(-1, 0) | neighbour_bit(1, 1);
+        assert_eq!(universe.neighbourhood(1, 1), expected);
+        assert_eq!(universe.live_neighbours(1, 1), 3);
+    }
+
+    #[test]
+    fn test_non_totalistic_rule_matches_sparse_universe() {
+        let rule = crate::resources::parse_rule("B2-a/S12").unwrap();
+        let cells = [(10, 10), (11, 10), (12, 11), (10, 12), (13, 13), (11, 13)];
+        let mut universe = Universe::new(Dimensions { width: 40, height: 40 }, Topology::Plane, rule);
+        let mut grid = Grid::new();
+        for &(x, y) in &cells {
+            universe.set(x, y, 1);
+            grid.insert((x as i32, y as i32), 1);
+        }
+        let mut sparse = SparseUniverse::from_grid(&grid, rule);
+        for _ in 0..8 {
+            universe.step();
+            sparse.step();
+            let mut expected = sparse.cells();
+            expected.sort_unstable_by_key(|&((x, y), _)| (y, x));
+            assert_eq!(universe.cells(), expected);
+        }
+    }
+
+    #[test]
+    fn test_map_rule_reads_neighbours_by_direction() {
+        // Every cell copies its north-west neighbour.
+        let rule = crate::resources::parse_rule(&format!("MAP{}D{}w", "A".repeat(42), "/".repeat(42))).unwrap();
+        let mut universe = Universe::new(Dimensions { width: 8, height: 8 }, Topology::Plane, rule);
+        universe.set(1, 1, 1);
+        universe.set(1, 3, 1);
+        universe.step_n(2);
+        assert_eq!(universe.cells(), vec![((3, 3), 1), ((3, 5), 1)]);
+    }
+
+    #[test]
+    fn test_wide_neighbourhood_reads_neighbours_by_direction() {
+        // Every cell copies the cell two up and two to the left.
+        let rule = crate::resources::parse_rule("B1/S1@8000000").unwrap();
+        let mut universe = Universe::new(Dimensions { width: 10, height: 10 }, Topology::Plane, rule);
+        universe.set(1, 1, 1);
+        universe.set(4, 1, 1);
+        universe.step_n(2);
+        assert_eq!(universe.cells(), vec![((5, 5), 1), ((8, 5), 1)]);
+        universe.step();
+        assert_eq!(universe.cells(), vec![((7, 7), 1)]);
+    }
+
+    #[test]
+    fn test_larger_than_life_matches_sparse_universe() {
+        let rule = crate::resources::parse_rule("R5,C0,M1,S34..58,B34..45,NM").unwrap();
+        let mut universe = Universe::new(Dimensions { width: 80, height: 80 }, Topology::Plane, rule);
+        let mut grid = Grid::new();
+        for (x, y) in (30..50).flat_map(|x| (30..50).map(move |y| (x, y))).filter(|(x, y)| (x * 7 + y * 13) % 8 < 3) {
+            universe.set(x, y, 1);
+            grid.insert((x as i32, y as i32), 1);
+        }
+        let mut sparse = SparseUniverse::from_grid(&grid, rule);
+        for _ in 0..10 {
+            universe.step();
+            sparse.step();
+            let mut expected = sparse.cells();
+            expected.sort_unstable_by_key(|&((x, y), _)| (y, x));
+            assert_eq!(universe.cells(), expected);
+        }
+        assert!(universe.population() > 0);
+    }
+
+    #[test]
+    fn test_larger_than_life_wraps_like_conway() {
+        let dimensions = Dimensions { width: 12, height: 9 };
+        let larger = crate::resources::parse_rule("R1,C0,M0,S2..3,B3..3,NM").unwrap();
+        for topology in [Topology::TORUS, Topology::KleinBottle { horizontal_twist: true }] {
+            let mut conway = Universe::new(dimensions, topology, Rule::conway());
+            let mut universe = Universe::new(dimensions, topology, larger);
+            for (x, y) in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+                conway.set(x, y, 1);
+                universe.set(x, y, 1);
+            }
+            for _ in 0..40 {
+                conway.step();
+                universe.step();
+                assert_eq!(universe.cells, conway.cells, "{:?}", topology);
+            }
+        }
+    }
+
+    #[test]
+    fn test_named_rule_matches_sparse_universe() {
+        let pattern = crate::library::find("langtons-loop").unwrap().pattern();
+        let rule = pattern.rule.unwrap();
+        let mut universe = Universe::new(Dimensions { width: 50, height: 50 }, Topology::Plane, rule);
+        let mut grid = Grid::new();
+        for (&(x, y), &state) in &pattern.cells {
+            universe.set(x as i64 + 15, y as i64 + 15, state as u8);
+            grid.insert((x + 15, y + 15), state);
+        }
+        let mut sparse = SparseUniverse::from_grid(&grid, rule);
+        for _ in 0..151 {
+            universe.step();
+            sparse.step();
+        }
+        let mut expected = sparse.cells();
+        expected.sort_unstable_by_key(|&((x, y), _)| (y, x));
+        assert_eq!(universe.cells(), expected);
+    }
+
+    #[test]
+    fn test_wide_neighbourhood_matches_sparse_universe() {
+        let rule = crate::resources::parse_rule("B3,4,5/S2,3,4,5,6V2").unwrap();
+        let mut universe = Universe::new(Dimensions { width: 60, height: 60 }, Topology::Plane, rule);
+        let mut grid = Grid::new();
+        for (x, y) in [(28, 28), (29, 28), (30, 29), (28, 30), (31, 31), (29, 31), (30, 30)] {
+            universe.set(x, y, 1);
+            grid.insert((x as i32, y as i32), 1);
+        }
+        let mut sparse = SparseUniverse::from_grid(&grid, rule);
+        for _ in 0..10 {
+            universe.step();
+            sparse.step();
+            let mut expected = sparse.cells();
+            expected.sort_unstable_by_key(|&((x, y), _)| (y, x));
+            assert_eq!(universe.cells(), expected);
+        }
+    }
+
+    #[test]
+    fn test_from_grid() {
+        let mut grid = Grid::new();
+        grid.insert((1, 1), 1);
+        grid.insert((0, 0), 0);
+        let universe = Universe::from_grid(&grid, Dimensions { width: 3, height: 3 }, Topology::Plane, Rule::conway());
+        assert_eq!(universe.get(1, 1), 1);
+        assert_eq!(universe.population(), 1);
+    }
+
+    #[test]
+    fn test_cells_lists_every_non_dead_cell() {
+        let mut universe = universe_with(&[(3, 0), (1, 2)], 4, 3, Topology::Plane);
+        universe.set(0, 0, 2);
+        assert_eq!(universe.cells(), vec![((0, 0), 2), ((3, 0), 1), ((1, 2), 1)]);
+    }
+
+    #[test]
+    fn test_changed_cells() {
+        let mut universe = universe_with(&[(2, 1), (2, 2), (2, 3)], 5, 5, Topology::Plane);
+        assert_eq!(universe.changed_cells(), Some(vec![(2, 1), (2, 2), (2, 3)]));
+        universe.step();
+        assert_eq!(universe.changed_cells(), Some(vec![(2, 1), (1, 2), (3, 2), (2, 3)]));
+
+        let mut block = universe_with(&[(1, 1), (1, 2), (2, 1), (2, 2)], 5, 5, Topology::Plane);
+        block.step();
+        assert_eq!(block.changed_cells(), Some(vec![]));
+        block.set(4, 4, 1);
+        assert_eq!(block.changed_cells(), Some(vec![(4, 4)]));
+    }
+
+    #[test]
+    fn test_active_region_matches_full_evaluation() {
+        let dimensions = Dimensions { width: 40, height: 40 };
+        let topologies = [
+            Topology::Plane,
+            Topology::TORUS,
+            Topology::Torus { horizontal_shift: 3, vertical_shift: 0 },
+            Topology::KleinBottle { horizontal_twist: true },
+            Topology::KleinBottle { horizontal_twist: false },
+            Topology::CrossSurface,
+            Topology::Sphere,
+        ];
+        let rules = [
+            "B3/S23",
+            "B2/S/C4",
+            "B0123478/S01234678",
+            "B2/S013V",
+            "B24/S34H",
+            "B3,4,5/S2,3,4,5,6M2",
+            "B1/S1@8000000",
+        ];
+        for topology in topologies {
+            for rule in rules {
+                let rule = crate::resources::parse_rule(rule).unwrap();
+                let mut active = Universe::new(dimensions, topology, rule);
+                let mut full = Universe::new(dimensions, topology, rule);
+                for (x, y) in [(0, 0), (1, 0), (2, 0), (39, 38), (39, 39), (38, 39), (20, 19), (20, 20), (21, 20)] {
+                    active.set(x, y, 1);
+                    full.set(x, y, 1);
+                }
+                for generation in 0..60 {
+                    active.step();
+                    full.step_everything();
+                    assert_eq!(active.cells, full.cells, "{:?} {} generation {}", topology, rule, generation);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_threaded_steps_match_serial_steps() {
+        let dimensions = Dimensions { width: 37, height: 23 };
+        let mut grid = Grid::new();
+        for x in 0..dimensions.width {
+            for y in 0..dimensions.height {
+                grid.insert((x, y), ((x * 7 + y * 13) % 5 == 0) as i32);
+            }
+        }
+        let rule = crate::resources::parse_rule("B3/S23/C4").unwrap();
+        let mut serial = Universe::from_grid(&grid, dimensions, Topology::TORUS, rule);
+        let mut threaded = Universe::from_grid(&grid, dimensions, Topology::TORUS, rule).with_threads(4);
+        for _ in 0..30 {
+            serial.step();
+            threaded.step();
+            assert_eq!(serial.cells, threaded.cells);
+        }
+    }
+
+    #[test]
+    fn test_threaded_active_region_matches_serial() {
+        let dimensions = Dimensions { width: 60, height: 60 };
+        let rule = crate::resources::parse_rule("B2/S/C3").unwrap();
+        let mut serial = Universe::new(dimensions, Topology::TORUS, rule);
+        let mut threaded = Universe::new(dimensions, Topology::TORUS, rule).with_threads(4);
+        for universe in [&mut serial, &mut threaded] {
+            universe.set(30, 30, 1);
+            universe.set(31, 30, 1);
+        }
+        for _ in 0..20 {
+            serial.step();
+            threaded.step();
+            assert_eq!(serial.cells, threaded.cells);
+        }
+        assert!(serial.population() > 0);
+    }
+
+    #[test]
+    fn test_parse_backend() {
+        assert_eq!("cells".parse(), Ok(Backend::Cells));
+        assert_eq!("Bits".parse(), Ok(Backend::Bits));
+        assert_eq!("hashlife".parse(), Ok(Backend::HashLife));
+        assert_eq!("gpu".parse::<Backend>(), Err(EngineError::UnknownBackend("gpu".to_string())));
+    }
+
+    #[test]
+    fn test_backends_build_equivalent_simulations() {
+        let mut grid = Grid::new();
+        for pos in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            grid.insert(pos, 1);
+        }
+        let dimensions = Dimensions { width: 8, height: 8 };
+        for backend in [Backend::Cells, Backend::Bits] {
+            let mut simulation = backend.build(&grid, dimensions, Topology::TORUS, Rule::conway(), 1).unwrap();
+            simulation.step_n(32);
+            assert_eq!(simulation.population(), 5, "{:?}", backend);
+            assert_eq!(simulation.get(1, 0), 1, "{:?}", backend);
+        }
+    }
+
+    #[test]
+    fn test_backends_build_equivalent_unbounded_simulations() {
+        let mut grid = Grid::new();
+        for pos in [(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)] {
+            grid.insert(pos, 1);
+        }
+        for backend in [Backend::Cells, Backend::HashLife] {
+            let mut simulation = backend.build_unbounded(&grid, Rule::conway()).unwrap();
+            simulation.step_n(400);
+            assert_eq!(simulation.population(), 5, "{:?}", backend);
+            assert_eq!(simulation.get(101, 100), 1, "{:?}", backend);
+        }
+        assert_eq!(Backend::Bits.build_unbounded(&grid, Rule::conway()).err(), Some(EngineError::Unbounded));
+        for rule in ["B0/S8", "B0/S2-a", "R2,C0,M0,S1..3,B0..2,NM"] {
+            let rule = crate::resources::parse_rule(rule).unwrap();
+            let unsupported = Some(EngineError::UnsupportedRule(rule.to_string()));
+            assert_eq!(Backend::Cells.build_unbounded(&grid, rule).err(), unsupported);
+            assert_eq!(Backend::HashLife.build_unbounded(&grid, rule).err(), unsupported);
+        }
+        let dimensions = Dimensions { width: 8, height: 8 };
+        assert_eq!(
+            Backend::HashLife.build(&grid, dimensions, Topology::Plane, Rule::conway(), 1).err(),
+            Some(EngineError::UnsupportedTopology(Topology::Plane))
+        );
+    }
+}
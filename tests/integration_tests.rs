@@ -1,39 +1,54 @@
 use bedelli::components::*;
 use bedelli::resources::*;
+use bedelli::sparse::SparseUniverse;
+use bedelli::systems::*;
+use bedelli::universe::{Simulation, Universe};
 use bedelli::Seeder;
 use legion::*;
-use std::collections::HashMap;
 
-fn create_world_with_pattern(pattern: Vec<(i32, i32)>, width: i32, height: i32) -> (World, Resources) {
+fn create_world_with_rule(
+    pattern: Vec<(i32, i32)>,
+    width: i32,
+    height: i32,
+    rule: Rule,
+    topology: Topology,
+) -> (World, Resources) {
     let mut world = World::default();
     let mut resources = Resources::default();
-    let mut position_index = HashMap::new();
+    let dimensions = Dimensions { width, height };
+    let mut universe = Universe::new(dimensions, topology, rule);
 
     for x in 0..width {
         for y in 0..height {
-            let alive = pattern.contains(&(x, y));
-            let entity = world.push((
+            let cell = if pattern.contains(&(x, y)) { Cell::ALIVE } else { Cell::DEAD };
+            universe.set(x as i64, y as i64, cell.state);
+            world.push((
                 Position { x, y },
-                Cell { alive },
+                cell,
+                NextCell { state: cell.state },
                 Age::default(),
                 CellColor::default(),
             ));
-            position_index.insert((x, y), entity);
         }
     }
 
-    resources.insert(Dimensions { width, height });
-    resources.insert(conway_rule as RuleFn);
-    resources.insert(PositionIndex(position_index));
+    resources.insert(dimensions);
+    resources.insert(Engine(Box::new(universe)));
+    resources.insert(Viewport::default());
+    resources.insert(InputState::default());
 
     (world, resources)
 }
 
+fn create_world_with_pattern(pattern: Vec<(i32, i32)>, width: i32, height: i32) -> (World, Resources) {
+    create_world_with_rule(pattern, width, height, Rule::conway(), Topology::Plane)
+}
+
 fn get_alive_positions(world: &World) -> Vec<(i32, i32)> {
     <(&Position, &Cell)>::query()
         .iter(world)
         .filter_map(|(pos, cell)| {
-            if cell.alive {
+            if cell.is_alive() {
                 Some((pos.x, pos.y))
             } else {
                 None
@@ -43,46 +58,14 @@ fn get_alive_positions(world: &World) -> Vec<(i32, i32)> {
 }
 
 fn simulate_step(world: &mut World, resources: &mut Resources) {
-    use std::collections::HashSet;
-
-    let dimensions = resources.get::<Dimensions>().unwrap();
-    let width = dimensions.width;
-    let height = dimensions.height;
-    let rule = *resources.get::<RuleFn>().unwrap();
-
-    let alive: HashSet<(i32, i32)> = get_alive_positions(world)
-        .into_iter()
-        .collect();
-
-    let offsets: [(i32, i32); 8] = [
-        (-1, -1), (-1, 0), (-1, 1),
-        (0, -1),           (0, 1),
-        (1, -1),  (1, 0),  (1, 1),
-    ];
-
-    let mut updates = Vec::new();
-    let mut query = <(Entity, &Position, &Cell)>::query();
-    for (entity, pos, cell) in query.iter(world) {
-        let mut alive_neighbours = 0;
-        for (dx, dy) in offsets {
-            let nx = pos.x + dx;
-            let ny = pos.y + dy;
-            if nx >= 0 && nx < width && ny >= 0 && ny < height && alive.contains(&(nx, ny)) {
-                alive_neighbours += 1;
-            }
-        }
-        
-        let new_state = rule(cell.alive, alive_neighbours);
-        updates.push((*entity, new_state));
-    }
-
-    for (entity, new_state) in updates {
-        if let Some(mut entry) = world.entry(entity) {
-            if let Ok(cell) = entry.get_component_mut::<Cell>() {
-                cell.alive = new_state;
-            }
-        }
-    }
+    let mut schedule = Schedule::builder()
+        .add_system(mouse_toggle_system())
+        .add_system(neighbor_counting_system())
+        .add_system(state_update_system())
+        .add_system(age_update_system())
+        .add_system(color_update_system())
+        .build();
+    schedule.execute(world, resources);
 }
 
 #[test]
@@ -205,3 +188,149 @@ fn test_multiple_simulation_steps() {
     let alive = get_alive_positions(&world);
     assert_eq!(alive.len(), 3);
 }
+
+#[test]
+fn test_brians_brain_cells_decay_before_dying() {
+    let pair = vec![(2, 2), (2, 3)];
+    let brians_brain = parse_rule("B2/S/C3").unwrap();
+    let (mut world, mut resources) = create_world_with_rule(pair, 6, 6, brians_brain, Topology::Plane);
+
+    simulate_step(&mut world, &mut resources);
+
+    let dying = <(&Position, &Cell)>::query()
+        .iter(&world)
+        .filter(|(_, cell)| cell.state == 2)
+        .count();
+    assert_eq!(dying, 2);
+    assert_eq!(get_alive_positions(&world).len(), 4);
+
+    simulate_step(&mut world, &mut resources);
+
+    let states: Vec<u8> = <(&Position, &Cell)>::query()
+        .iter(&world)
+        .filter(|(pos, _)| **pos == Position { x: 2, y: 2 } || **pos == Position { x: 2, y: 3 })
+        .map(|(_, cell)| cell.state)
+        .collect();
+    assert_eq!(states, vec![0, 0]);
+}
+
+#[test]
+fn test_glider_wraps_around_torus() {
+    let glider = vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+    let (mut world, mut resources) = create_world_with_rule(glider.clone(), 6, 6, Rule::conway(), Topology::TORUS);
+
+    for _ in 0..24 {
+        simulate_step(&mut world, &mut resources);
+        assert_eq!(get_alive_positions(&world).len(), 5);
+    }
+
+    let mut alive = get_alive_positions(&world);
+    alive.sort();
+    let mut expected = glider;
+    expected.sort();
+    assert_eq!(alive, expected);
+}
+
+#[test]
+fn test_glider_stops_at_plane_edge() {
+    let glider = vec![(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)];
+    let (mut world, mut resources) = create_world_with_pattern(glider, 6, 6);
+
+    for _ in 0..24 {
+        simulate_step(&mut world, &mut resources);
+    }
+
+    assert_ne!(get_alive_positions(&world).len(), 5);
+}
+
+#[test]
+fn test_ecs_mirrors_headless_universe() {
+    let r_pentomino = vec![(5, 4), (6, 4), (4, 5), (5, 5), (5, 6)];
+    let (mut world, mut resources) = create_world_with_pattern(r_pentomino.clone(), 12, 12);
+
+    let mut universe = Universe::new(Dimensions { width: 12, height: 12 }, Topology::Plane, Rule::conway());
+    for &(x, y) in &r_pentomino {
+        universe.set(x as i64, y as i64, 1);
+    }
+
+    for _ in 0..10 {
+        simulate_step(&mut world, &mut resources);
+    }
+    universe.step_n(10);
+
+    let mut expected: Vec<(i32, i32)> = (0..12)
+        .flat_map(|x| (0..12).map(move |y| (x, y)))
+        .filter(|&(x, y)| universe.get(x as i64, y as i64) == 1)
+        .collect();
+    expected.sort();
+    let mut alive = get_alive_positions(&world);
+    alive.sort();
+    assert_eq!(alive, expected);
+    assert_eq!(universe.population(), alive.len());
+}
+
+#[test]
+fn test_mouse_click_toggles_engine_cell() {
+    let (mut world, mut resources) = create_world_with_pattern(vec![(1, 2), (3, 2)], 5, 5);
+    {
+        let mut input = resources.get_mut::<InputState>().unwrap();
+        input.mouse_pos = (2, 2);
+        input.mouse_left = true;
+    }
+
+    simulate_step(&mut world, &mut resources);
+
+    let mut alive = get_alive_positions(&world);
+    alive.sort();
+    assert_eq!(alive, vec![(2, 1), (2, 2), (2, 3)]);
+    let engine = resources.get::<Engine>().unwrap();
+    assert_eq!(engine.0.generation(), 1);
+    assert_eq!(engine.0.population(), 3);
+}
+
+#[test]
+fn test_viewport_shows_unbounded_universe() {
+    let mut world = World::default();
+    let mut resources = Resources::default();
+    for x in 0..5 {
+        for y in 0..5 {
+            world.push((
+                Position { x, y },
+                Cell::DEAD,
+                NextCell { state: 0 },
+                Age::default(),
+                CellColor::default(),
+            ));
+        }
+    }
+
+    let mut universe = SparseUniverse::new(Rule::conway());
+    for (x, y) in [(1000, 999), (1000, 1000), (1000, 1001)] {
+        universe.set(x, y, 1);
+    }
+    resources.insert(Engine(Box::new(universe)));
+    resources.insert(Viewport { x: 998, y: 998 });
+    resources.insert(InputState::default());
+
+    simulate_step(&mut world, &mut resources);
+
+    let mut alive = get_alive_positions(&world);
+    alive.sort();
+    assert_eq!(alive, vec![(1, 2), (2, 2), (3, 2)]);
+}
+
+#[test]
+fn test_step_keeps_next_cell_on_every_entity() {
+    let blinker = vec![(2, 1), (2, 2), (2, 3)];
+    let (mut world, mut resources) = create_world_with_pattern(blinker, 5, 5);
+
+    simulate_step(&mut world, &mut resources);
+    simulate_step(&mut world, &mut resources);
+
+    let cells: Vec<(u8, u8)> = <(&Cell, &NextCell)>::query()
+        .iter(&world)
+        .map(|(cell, next)| (cell.state, next.state))
+        .collect();
+    assert_eq!(cells.len(), 25);
+    assert!(cells.iter().all(|(state, next)| state == next));
+}